@@ -6,11 +6,24 @@ use nonces::*;
 
 fn criterion_benchmark(c: &mut Criterion) {
     let iv_bytes: [u8; 12] = hex!("6fac81d4f2c3bebe02b8b375");
-    let iv_rustls = Iv::new(&iv_bytes).unwrap();
+    let iv_rustls = DynIv::new(&iv_bytes).unwrap();
+    let iv_const = Iv::new(iv_bytes);
 
     c.bench_function("rustls-nonce", |b| {
         b.iter(|| {
-            let _rustls_nonce_1 = Nonce::new(&iv_rustls, black_box(1));
+            let _rustls_nonce_1 = DynNonce::new(&iv_rustls, black_box(1));
+        })
+    });
+
+    c.bench_function("const-generic-nonce", |b| {
+        b.iter(|| {
+            let _const_generic_nonce_1 = Nonce::new(&iv_const, black_box(1));
+        })
+    });
+
+    c.bench_function("native-nonce", |b| {
+        b.iter(|| {
+            let _native_nonce_1 = NativeBackend::nonce(&iv_const, black_box(1));
         })
     });
 
@@ -0,0 +1,153 @@
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::{Aes128Gcm, Aes256Gcm};
+use chacha20poly1305::ChaCha20Poly1305;
+
+use crate::{ApiMisuse, Error, Nonce};
+
+/// Length of the AEAD tag appended to every sealed message.
+pub const TAG_LEN: usize = 16;
+
+/// The AEADs that the record and message layers in this crate can seal with, and that
+/// protect QUIC packets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AeadCipher {
+    Aes128Gcm,
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+impl AeadCipher {
+    /// Return the key length, which for QUIC is also the header protection key length.
+    pub const fn key_len(self) -> usize {
+        match self {
+            Self::Aes128Gcm => 16,
+            Self::Aes256Gcm | Self::ChaCha20Poly1305 => 32,
+        }
+    }
+}
+
+/// An AEAD key sealing and opening in place with a detached tag.
+///
+/// Failing to open is always [`Error::DecryptError`]; callers map it further if their
+/// protocol needs to.
+#[derive(Clone)]
+pub(crate) enum AeadKey {
+    Aes128Gcm(Box<Aes128Gcm>),
+    Aes256Gcm(Box<Aes256Gcm>),
+    ChaCha20Poly1305(Box<ChaCha20Poly1305>),
+}
+
+impl AeadKey {
+    pub(crate) fn new(cipher: AeadCipher, key: &[u8]) -> Result<Self, Error> {
+        let invalid = |_| ApiMisuse::KeyLengthMismatch {
+            expected: cipher.key_len(),
+            actual: key.len(),
+        };
+        Ok(match cipher {
+            AeadCipher::Aes128Gcm => {
+                Self::Aes128Gcm(Box::new(Aes128Gcm::new_from_slice(key).map_err(invalid)?))
+            }
+            AeadCipher::Aes256Gcm => {
+                Self::Aes256Gcm(Box::new(Aes256Gcm::new_from_slice(key).map_err(invalid)?))
+            }
+            AeadCipher::ChaCha20Poly1305 => Self::ChaCha20Poly1305(Box::new(
+                ChaCha20Poly1305::new_from_slice(key).map_err(invalid)?,
+            )),
+        })
+    }
+
+    pub(crate) fn seal(&self, nonce: &Nonce, aad: &[u8], buf: &mut [u8]) -> [u8; TAG_LEN] {
+        let nonce = nonce.as_bytes().into();
+        match self {
+            Self::Aes128Gcm(aead) => aead.encrypt_in_place_detached(nonce, aad, buf),
+            Self::Aes256Gcm(aead) => aead.encrypt_in_place_detached(nonce, aad, buf),
+            Self::ChaCha20Poly1305(aead) => aead.encrypt_in_place_detached(nonce, aad, buf),
+        }
+        .expect("payload length is within AEAD limits")
+        .into()
+    }
+
+    pub(crate) fn open(
+        &self,
+        nonce: &Nonce,
+        aad: &[u8],
+        buf: &mut [u8],
+        tag: &[u8],
+    ) -> Result<(), Error> {
+        let nonce = nonce.as_bytes().into();
+        let tag = tag.into();
+        match self {
+            Self::Aes128Gcm(aead) => aead.decrypt_in_place_detached(nonce, aad, buf, tag),
+            Self::Aes256Gcm(aead) => aead.decrypt_in_place_detached(nonce, aad, buf, tag),
+            Self::ChaCha20Poly1305(aead) => aead.decrypt_in_place_detached(nonce, aad, buf, tag),
+        }
+        .map_err(|_| Error::DecryptError)
+    }
+}
+
+/// Reads big-endian fields from the front of a byte slice.
+///
+/// Running out of input is always [`Error::Truncated`].
+pub(crate) struct Reader<'a>(pub(crate) &'a [u8]);
+
+impl<'a> Reader<'a> {
+    pub(crate) fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            return Err(Error::Truncated);
+        }
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    /// A variable-length integer, RFC 9000 section 16.
+    pub(crate) fn varint(&mut self) -> Result<u64, Error> {
+        let first = self.u8()?;
+        let len = 1 << (first >> 6);
+        let rest = self.take(len - 1)?;
+        Ok(rest
+            .iter()
+            .fold(u64::from(first & 0x3f), |v, b| (v << 8) | u64::from(*b)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::Iv;
+    use hex_literal::hex;
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            AeadKey::new(AeadCipher::Aes256Gcm, &[0; 16]),
+            Err(Error::Api(ApiMisuse::KeyLengthMismatch {
+                expected: 32,
+                actual: 16
+            }))
+        ));
+
+        let key = AeadKey::new(AeadCipher::ChaCha20Poly1305, &[7; 32]).unwrap();
+        let nonce = Nonce::new(&Iv::new(hex!("000102030405060708090a0b")), 1);
+        let mut buf = *b"message";
+        let mut tag = key.seal(&nonce, b"aad", &mut buf);
+        tag[0] ^= 1;
+        assert!(matches!(
+            key.open(&nonce, b"aad", &mut buf, &tag),
+            Err(Error::DecryptError)
+        ));
+
+        let mut reader = Reader(&hex!("4025"));
+        assert_eq!(reader.varint().unwrap(), 0x25);
+        assert!(matches!(reader.u8(), Err(Error::Truncated)));
+        assert!(matches!(
+            Reader(&hex!("80ff")).varint(),
+            Err(Error::Truncated)
+        ));
+    }
+}
@@ -0,0 +1,206 @@
+#[cfg(doc)]
+use crate::DynNonce;
+use crate::{Iv, Nonce, xor};
+
+/// An implementation of the `iv ^ seq` nonce construction.
+///
+/// [`Nonce::new`], [`Nonce::quic`] and their [`DynNonce`] counterparts go through
+/// [`DefaultBackend`], which is selected
+/// with the `backend-native` or `backend-crypto-bigint` cargo features and falls back
+/// to [`RustlsBackend`].  If both features are enabled, `backend-crypto-bigint` wins.
+pub trait NonceBackend {
+    /// Return `iv ^ word`, where `word` is a big-endian integer aligned to the end of `iv`.
+    ///
+    /// Callers guarantee that `word` fits in `N` bytes.
+    fn xor_be<const N: usize>(iv: &[u8; N], word: u128) -> [u8; N];
+
+    /// Write `iv ^ word` into `out`, which is as long as `iv`, for IVs whose length is
+    /// only known at runtime.
+    ///
+    /// Callers guarantee that `word` fits in `iv.len()` bytes.
+    fn xor_be_slice(iv: &[u8], word: u128, out: &mut [u8]);
+
+    /// Combine an `Iv` and sequence number to produce a unique nonce.
+    #[inline]
+    fn nonce<const N: usize>(iv: &Iv<N>, seq: u64) -> Nonce<N> {
+        const { assert!(N >= 8, "nonce is too short for a 64-bit sequence number") };
+        Nonce(Self::xor_be(&iv.0, seq.into()))
+    }
+
+    /// Creates a unique nonce based on the multipath `path_id`, the `iv` and packet number `pn`.
+    #[inline]
+    fn quic<const N: usize>(path_id: Option<u32>, iv: &Iv<N>, pn: u64) -> Nonce<N> {
+        const {
+            assert!(
+                N >= 12,
+                "nonce is too short for a path ID and packet number"
+            )
+        };
+        Nonce(Self::xor_be(&iv.0, quic_word(path_id, pn)))
+    }
+}
+
+/// The big-endian integer formed by concatenating `path_id` (or 0) and `pn`.
+#[inline]
+pub(crate) fn quic_word(path_id: Option<u32>, pn: u64) -> u128 {
+    (u128::from(path_id.unwrap_or(0)) << 64) | u128::from(pn)
+}
+
+/// The backend selected by cargo features.
+#[cfg(feature = "backend-crypto-bigint")]
+pub type DefaultBackend = crate::CryptoBigInt;
+
+/// The backend selected by cargo features.
+#[cfg(all(feature = "backend-native", not(feature = "backend-crypto-bigint")))]
+pub type DefaultBackend = NativeBackend;
+
+/// The backend selected by cargo features.
+#[cfg(not(any(feature = "backend-native", feature = "backend-crypto-bigint")))]
+pub type DefaultBackend = RustlsBackend;
+
+/// The byte loop from rustls.
+pub struct RustlsBackend;
+
+impl NonceBackend for RustlsBackend {
+    #[inline]
+    fn xor_be<const N: usize>(iv: &[u8; N], word: u128) -> [u8; N] {
+        let mut buf = [0u8; N];
+        Self::xor_be_slice(iv, word, &mut buf);
+        buf
+    }
+
+    #[inline]
+    fn xor_be_slice(iv: &[u8], word: u128, out: &mut [u8]) {
+        let word = word.to_be_bytes();
+        let len = iv.len().min(16);
+        out.copy_from_slice(iv);
+        xor(&mut out[iv.len() - len..], &word[16 - len..]);
+    }
+}
+
+/// Native `u64` / `u128` register arithmetic.
+///
+/// IVs of 16 bytes or more are XORed as one `u128`, shorter ones as a `u64` sequence
+/// number plus a `u32` path ID.  IVs under 8 bytes fall back to the byte loop.
+pub struct NativeBackend;
+
+impl NonceBackend for NativeBackend {
+    #[inline]
+    fn xor_be<const N: usize>(iv: &[u8; N], word: u128) -> [u8; N] {
+        let mut buf = *iv;
+        if N >= 16 {
+            let tail: &mut [u8; 16] = (&mut buf[N - 16..]).try_into().unwrap();
+            *tail = (u128::from_be_bytes(*tail) ^ word).to_be_bytes();
+        } else if N >= 8 {
+            let seq: &mut [u8; 8] = (&mut buf[N - 8..]).try_into().unwrap();
+            *seq = (u64::from_be_bytes(*seq) ^ word as u64).to_be_bytes();
+            if N >= 12 {
+                let path_id: &mut [u8; 4] = (&mut buf[N - 12..N - 8]).try_into().unwrap();
+                *path_id = (u32::from_be_bytes(*path_id) ^ (word >> 64) as u32).to_be_bytes();
+            }
+        } else {
+            xor(&mut buf, &(word as u64).to_be_bytes()[8 - N..]);
+        }
+        buf
+    }
+
+    #[inline]
+    fn xor_be_slice(iv: &[u8], word: u128, out: &mut [u8]) {
+        let len = iv.len();
+        out.copy_from_slice(iv);
+        if len >= 16 {
+            let tail: &mut [u8; 16] = (&mut out[len - 16..]).try_into().unwrap();
+            *tail = (u128::from_be_bytes(*tail) ^ word).to_be_bytes();
+        } else if len >= 8 {
+            let seq: &mut [u8; 8] = (&mut out[len - 8..]).try_into().unwrap();
+            *seq = (u64::from_be_bytes(*seq) ^ word as u64).to_be_bytes();
+            if len >= 12 {
+                let path_id: &mut [u8; 4] = (&mut out[len - 12..len - 8]).try_into().unwrap();
+                *path_id = (u32::from_be_bytes(*path_id) ^ (word >> 64) as u32).to_be_bytes();
+            }
+        } else {
+            xor(out, &(word as u64).to_be_bytes()[8 - len..]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::{CryptoBigInt, DynIv, DynNonce};
+    use hex_literal::hex;
+
+    fn check<const N: usize>(iv: [u8; N]) {
+        let iv = Iv::new(iv);
+        for (path_id, pn) in [(None, 0), (Some(1), 1), (Some(u32::MAX), u64::MAX)] {
+            let expected = RustlsBackend::quic(path_id, &iv, pn);
+            assert_eq!(NativeBackend::quic(path_id, &iv, pn), expected);
+            assert_eq!(CryptoBigInt::quic(path_id, &iv, pn), expected);
+            assert_eq!(Nonce::quic(path_id, &iv, pn), expected);
+        }
+    }
+
+    #[test]
+    fn backends_agree() {
+        check(hex!("6fac81d4f2c3bebe02b8b375"));
+        check(hex!("6fac81d4f2c3bebe02b8b3756fac81d4"));
+        check(hex!("404142434445464748494a4b4c4d4e4f5051525354555657"));
+        check(hex!(
+            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"
+        ));
+        // longer than any crypto-bigint word
+        check([0xa5; 40]);
+
+        let iv = Iv::new(hex!("0001020304050607"));
+        assert_eq!(
+            NativeBackend::nonce(&iv, 0x0102),
+            RustlsBackend::nonce(&iv, 0x0102)
+        );
+        assert_eq!(
+            CryptoBigInt::nonce(&iv, 0x0102),
+            RustlsBackend::nonce(&iv, 0x0102)
+        );
+    }
+
+    #[test]
+    fn backends_agree_on_slices() {
+        let bytes: [u8; 32] = core::array::from_fn(|i| 0x40 + i as u8);
+        for len in 1..=32 {
+            let iv = &bytes[..len];
+            for (path_id, pn) in [(None, 0), (None, 0x42), (Some(7), 0x0102_0304)] {
+                if (len < 8 && pn >> (8 * len) != 0) || (path_id.is_some() && len < 12) {
+                    continue;
+                }
+                let word = quic_word(path_id, pn);
+                let mut expected = vec![0; len];
+                RustlsBackend::xor_be_slice(iv, word, &mut expected);
+                let mut out = vec![0; len];
+                NativeBackend::xor_be_slice(iv, word, &mut out);
+                assert_eq!(out, expected, "len {len}");
+                CryptoBigInt::xor_be_slice(iv, word, &mut out);
+                assert_eq!(out, expected, "len {len}");
+                let nonce = DynNonce::try_quic(path_id, &DynIv::new(iv).unwrap(), pn).unwrap();
+                assert_eq!(nonce.as_bytes(), &expected[..], "len {len}");
+            }
+        }
+    }
+
+    #[test]
+    fn short_iv() {
+        let iv = hex!("a0b1c2d3");
+        let expected = hex!("a0b1c0d1");
+        assert_eq!(RustlsBackend::xor_be(&iv, 0x0202), expected);
+        assert_eq!(NativeBackend::xor_be(&iv, 0x0202), expected);
+        assert_eq!(CryptoBigInt::xor_be(&iv, 0x0202), expected);
+    }
+
+    #[test]
+    fn rustls_backend() {
+        let iv = Iv::new(hex!("6fac81d4f2c3bebe02b8b375"));
+        assert_eq!(
+            RustlsBackend::quic(Some(7), &iv, 1).to_array(),
+            hex!("6fac81d3f2c3bebe02b8b374")
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use crate::reconstruct::closest;
+use crate::tls13::hkdf_expand_label;
+use crate::{
+    ApiMisuse, Error, HashAlgorithm, HeaderProtectionKey, Iv, NONCE_LEN, Nonce, NonceSequence,
+};
+
+/// The DTLS sequence number is 48 bits, so the limit is 2^48.
+pub const DTLS_SEQ_LIMIT: u64 = NonceSequence::<NONCE_LEN>::DTLS_LIMIT;
+
+/// The DTLS version, which decides whether the epoch is part of the nonce input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DtlsVersion {
+    /// DTLS 1.2, RFC 6347: the nonce input is the 16-bit epoch followed by the 48-bit
+    /// sequence number, as in RFC 5288 and RFC 7905.
+    V1_2,
+    /// DTLS 1.3, RFC 9147: every epoch has its own keys and the nonce input is the
+    /// 48-bit sequence number.
+    V1_3,
+}
+
+/// Build the nonce for record (`epoch`, `seq`).
+///
+/// Returns an error if `seq` is 2^48 or more, or if `epoch` does not fit in 16 bits
+/// for DTLS 1.2.
+pub fn dtls_nonce<const N: usize>(
+    version: DtlsVersion,
+    iv: &Iv<N>,
+    epoch: u64,
+    seq: u64,
+) -> Result<Nonce<N>, Error> {
+    if seq >= DTLS_SEQ_LIMIT {
+        return Err(Error::SequenceExhausted {
+            limit: DTLS_SEQ_LIMIT,
+        });
+    }
+    Ok(Nonce::new(&epoch_iv(version, iv, epoch)?, seq))
+}
+
+/// Fold the epoch into the top 16 bits of the 64-bit sequence number part of the IV.
+fn epoch_iv<const N: usize>(version: DtlsVersion, iv: &Iv<N>, epoch: u64) -> Result<Iv<N>, Error> {
+    match version {
+        DtlsVersion::V1_2 => {
+            let epoch = u16::try_from(epoch).map_err(|_| ApiMisuse::EpochExceedsWidth { epoch })?;
+            Ok(Iv::new(Nonce::new(iv, u64::from(epoch) << 48).to_array()))
+        }
+        DtlsVersion::V1_3 => Ok(iv.clone()),
+    }
+}
+
+/// Nonces for the records of one DTLS epoch.
+///
+/// For DTLS 1.2 AES-GCM and AES-CCM, `iv` is the 4-byte salt followed by eight zero
+/// bytes, see [`crate::Tls12Nonces::iv`].
+#[derive(Clone)]
+pub struct DtlsEpochNonces<const N: usize = NONCE_LEN> {
+    epoch: u64,
+    sequence: NonceSequence<N>,
+}
+
+impl<const N: usize> DtlsEpochNonces<N> {
+    /// Start the sequence of `epoch` at zero.
+    pub fn new(version: DtlsVersion, epoch: u64, iv: &Iv<N>) -> Result<Self, Error> {
+        Ok(Self {
+            epoch,
+            sequence: NonceSequence::with_limit(epoch_iv(version, iv, epoch)?, DTLS_SEQ_LIMIT),
+        })
+    }
+
+    /// Return the sequence number and nonce for the next record to send.
+    pub fn next_record(&mut self) -> Result<(u64, Nonce<N>), Error> {
+        let seq = self.sequence.next_seq();
+        Ok((seq, self.sequence.next_nonce()?))
+    }
+
+    /// Return the nonce for a received record with sequence number `seq`.
+    pub fn nonce_for(&self, seq: u64) -> Result<Nonce<N>, Error> {
+        if seq >= DTLS_SEQ_LIMIT {
+            return Err(Error::SequenceExhausted {
+                limit: DTLS_SEQ_LIMIT,
+            });
+        }
+        Ok(Nonce::new(self.sequence.iv(), seq))
+    }
+
+    /// Return the epoch.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+}
+
+impl<const N: usize> Iv<N> {
+    /// Derive the write IV from a DTLS 1.3 traffic secret.
+    ///
+    /// This is `HKDF-Expand-Label(secret, "iv", "", N)` with the "dtls13" label prefix.
+    pub fn from_dtls13_secret(hash: HashAlgorithm, secret: &[u8]) -> Result<Self, Error> {
+        let mut iv = [0u8; N];
+        hkdf_expand_label(hash, secret, b"dtls13", b"iv", &[], &mut iv)?;
+        Ok(Self::new(iv))
+    }
+}
+
+/// Derive the record number encryption key from a DTLS 1.3 traffic secret.
+///
+/// This is `HKDF-Expand-Label(secret, "sn", "", K)` with the "dtls13" label prefix,
+/// RFC 9147 section 4.2.3.
+pub fn dtls13_sn_key<const K: usize>(hash: HashAlgorithm, secret: &[u8]) -> Result<[u8; K], Error> {
+    let mut key = [0u8; K];
+    hkdf_expand_label(hash, secret, b"dtls13", b"sn", &[], &mut key)?;
+    Ok(key)
+}
+
+/// Encrypt or decrypt the 1 or 2 byte sequence number of a DTLS 1.3 unified header.
+///
+/// The mask is computed from the first 16 bytes of the record `ciphertext`, RFC 9147
+/// section 4.2.3.  Applying it twice restores the original bytes.
+///
+/// Returns an error if `seq` is not 1 or 2 bytes long.
+pub fn dtls13_record_number_mask(
+    key: &HeaderProtectionKey,
+    ciphertext: &[u8],
+    seq: &mut [u8],
+) -> Result<(), Error> {
+    if !(1..=2).contains(&seq.len()) {
+        return Err(ApiMisuse::SequenceNumberLength { len: seq.len() }.into());
+    }
+    let mask = key.mask_from(ciphertext)?;
+    seq.iter_mut().zip(mask).for_each(|(s, m)| *s ^= m);
+    Ok(())
+}
+
+/// Rebuild the full sequence number from the low `bits` (8 or 16) bits in a DTLS 1.3
+/// unified header.
+///
+/// This picks the value closest to `expected`, which is one plus the highest sequence
+/// number successfully deprotected in the epoch, RFC 9147 section 4.2.2.
+///
+/// Returns an error if `bits` is not 8 or 16.
+pub fn reconstruct_dtls13_seq(expected: u64, truncated: u16, bits: u32) -> Result<u64, Error> {
+    if bits != 8 && bits != 16 {
+        return Err(ApiMisuse::TruncatedWidth { bits }.into());
+    }
+    let truncated = u64::from(truncated) & ((1 << bits) - 1);
+    Ok(closest(expected, truncated, bits, DTLS_SEQ_LIMIT))
+}
+
+/// Rebuild the full epoch from the low 2 bits in a DTLS 1.3 unified header.
+///
+/// This picks the epoch closest to `current`.
+pub fn reconstruct_dtls13_epoch(current: u64, low_bits: u8) -> u64 {
+    closest(current, u64::from(low_bits & 0b11), 2, u64::MAX)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn dtls12_epoch_in_nonce() {
+        let iv = Iv::new(hex!("6fac81d4f2c3bebe02b8b375"));
+        let nonce = dtls_nonce(DtlsVersion::V1_2, &iv, 0x0001, 0x0000_0000_0002).unwrap();
+        assert_eq!(nonce.to_array(), hex!("6fac81d4f2c2bebe02b8b377"));
+
+        let mut nonces = DtlsEpochNonces::new(DtlsVersion::V1_2, 1, &iv).unwrap();
+        nonces.next_record().unwrap();
+        nonces.next_record().unwrap();
+        assert_eq!(nonces.next_record().unwrap(), (2, nonce.clone()));
+        assert_eq!(nonces.nonce_for(2).unwrap(), nonce);
+
+        assert!(matches!(
+            dtls_nonce(DtlsVersion::V1_2, &iv, 0x1_0000, 0),
+            Err(Error::Api(ApiMisuse::EpochExceedsWidth { epoch: 0x1_0000 }))
+        ));
+    }
+
+    #[test]
+    fn dtls13_seq_only() {
+        let iv = Iv::new(hex!("6fac81d4f2c3bebe02b8b375"));
+        let nonce = dtls_nonce(DtlsVersion::V1_3, &iv, 3, 2).unwrap();
+        assert_eq!(nonce, Nonce::new(&iv, 2));
+    }
+
+    #[test]
+    fn seq_overflow() {
+        let iv = Iv::new(hex!("6fac81d4f2c3bebe02b8b375"));
+        assert!(dtls_nonce(DtlsVersion::V1_3, &iv, 0, DTLS_SEQ_LIMIT - 1).is_ok());
+        assert!(matches!(
+            dtls_nonce(DtlsVersion::V1_3, &iv, 0, DTLS_SEQ_LIMIT),
+            Err(Error::SequenceExhausted {
+                limit: DTLS_SEQ_LIMIT
+            })
+        ));
+
+        let nonces = DtlsEpochNonces::new(DtlsVersion::V1_2, 0, &iv).unwrap();
+        assert!(nonces.nonce_for(DTLS_SEQ_LIMIT).is_err());
+    }
+
+    #[test]
+    fn reconstruct_seq() {
+        // RFC 9000 appendix A.3 uses the same closest-value rule
+        assert_eq!(
+            reconstruct_dtls13_seq(0xa82f30eb, 0x9b32, 16).unwrap(),
+            0xa82f9b32
+        );
+
+        assert_eq!(reconstruct_dtls13_seq(0, 0x00, 8).unwrap(), 0);
+        assert_eq!(reconstruct_dtls13_seq(0xfe, 0x01, 8).unwrap(), 0x101);
+        assert_eq!(reconstruct_dtls13_seq(0x101, 0xff, 8).unwrap(), 0xff);
+        assert_eq!(reconstruct_dtls13_seq(0x180, 0x00, 8).unwrap(), 0x200);
+        assert_eq!(reconstruct_dtls13_seq(0x17f, 0x00, 8).unwrap(), 0x100);
+        assert_eq!(reconstruct_dtls13_seq(0x10, 0xf0, 8).unwrap(), 0xf0);
+        assert_eq!(
+            reconstruct_dtls13_seq(DTLS_SEQ_LIMIT - 1, 0x0001, 16).unwrap(),
+            DTLS_SEQ_LIMIT - 0xffff
+        );
+        assert!(matches!(
+            reconstruct_dtls13_seq(0, 0, 12),
+            Err(Error::Api(ApiMisuse::TruncatedWidth { bits: 12 }))
+        ));
+    }
+
+    #[test]
+    fn reconstruct_epoch() {
+        assert_eq!(reconstruct_dtls13_epoch(3, 3), 3);
+        assert_eq!(reconstruct_dtls13_epoch(3, 0), 4);
+        assert_eq!(reconstruct_dtls13_epoch(4, 3), 3);
+        assert_eq!(reconstruct_dtls13_epoch(0, 2), 2);
+        assert_eq!(reconstruct_dtls13_epoch(9, 1), 9);
+    }
+
+    #[test]
+    fn record_number_mask() {
+        let key = HeaderProtectionKey::aes(&hex!("000102030405060708090a0b0c0d0e0f")).unwrap();
+        // FIPS-197 appendix C.1, the mask starts 69c4
+        let ciphertext = hex!("00112233445566778899aabbccddeeff0102");
+
+        let mut seq = hex!("1234");
+        dtls13_record_number_mask(&key, &ciphertext, &mut seq).unwrap();
+        assert_eq!(seq, hex!("7bf0"));
+        dtls13_record_number_mask(&key, &ciphertext, &mut seq).unwrap();
+        assert_eq!(seq, hex!("1234"));
+
+        assert!(dtls13_record_number_mask(&key, &ciphertext[..15], &mut seq).is_err());
+        for len in [0, 3] {
+            assert!(matches!(
+                dtls13_record_number_mask(&key, &ciphertext, &mut [0; 3][..len]),
+                Err(Error::Api(ApiMisuse::SequenceNumberLength { .. }))
+            ));
+        }
+        assert!(matches!(
+            HeaderProtectionKey::aes(&[0; 24]),
+            Err(Error::Api(ApiMisuse::KeyLengthUnsupported {
+                supported: &[16, 32],
+                actual: 24
+            }))
+        ));
+    }
+
+    // RFC 7905 section 2: for DTLS the 64-bit sequence number is the 16-bit epoch
+    // followed by the 48-bit sequence number, padded on the left with four zero bytes
+    // and XORed with the IV.
+    #[test]
+    fn dtls12_chacha20_poly1305_nonce() {
+        let iv = Iv::new(hex!("6fac81d4f2c3bebe02b8b375"));
+        assert_eq!(
+            dtls_nonce(DtlsVersion::V1_2, &iv, 1, 0x0a0b_0c0d_0e0f)
+                .unwrap()
+                .to_array(),
+            hex!("6fac81d4f2c2b4b50eb5bd7a")
+        );
+    }
+
+    // RFC 9147 section 5.9 replaces the "tls13 " label prefix with "dtls13", so the
+    // HkdfLabel for the record number key is 0010 08 "dtls13sn" 00.  The secret is the
+    // client handshake traffic secret of RFC 8448 section 3.
+    #[test]
+    fn dtls13_sn_key_and_mask() {
+        let secret = hex!("b3eddb126e067f35a780b3abf45e2d8f3b1a950738f52e9600746a0e27a55a21");
+        let sn_key = dtls13_sn_key::<16>(HashAlgorithm::Sha256, &secret).unwrap();
+        assert_eq!(sn_key, hex!("0b946d65c5fb512499b199f9369d7e9f"));
+        assert_eq!(
+            Iv::<12>::from_dtls13_secret(HashAlgorithm::Sha256, &secret)
+                .unwrap()
+                .as_ref(),
+            &hex!("ec5c81f0f474f73053818414")
+        );
+
+        // the mask is AES-ECB(sn_key, ciphertext[0..16]), which starts 3bc0
+        let key = HeaderProtectionKey::aes(&sn_key).unwrap();
+        let ciphertext = hex!("d9313225f88406e5a55909c5aff5269a");
+        let mut seq = hex!("0005");
+        dtls13_record_number_mask(&key, &ciphertext, &mut seq).unwrap();
+        assert_eq!(seq, hex!("3bc5"));
+        let mut seq = hex!("05");
+        dtls13_record_number_mask(&key, &ciphertext, &mut seq).unwrap();
+        assert_eq!(seq, hex!("3e"));
+    }
+
+    #[test]
+    fn dtls13_label_prefix() {
+        let secret = [1; 32];
+        let dtls = Iv::<12>::from_dtls13_secret(HashAlgorithm::Sha256, &secret).unwrap();
+        let tls = Iv::<12>::from_tls13_secret(HashAlgorithm::Sha256, &secret).unwrap();
+        assert_ne!(dtls.as_ref(), tls.as_ref());
+        assert_ne!(
+            dtls13_sn_key::<16>(HashAlgorithm::Sha256, &secret).unwrap(),
+            crate::tls13_write_key::<16>(HashAlgorithm::Sha256, &secret).unwrap()
+        );
+    }
+}
@@ -0,0 +1,397 @@
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use crate::aead::{AeadCipher, AeadKey, TAG_LEN};
+use crate::{ApiMisuse, Error, Iv, NonceSequence};
+
+/// Length of the salt in an aes128gcm header.
+pub const ECE_SALT_LEN: usize = 16;
+
+/// The smallest record size an aes128gcm header may carry.
+pub const ECE_MIN_RECORD_SIZE: u32 = TAG_LEN as u32 + 2;
+
+const DELIMITER: u8 = 0x01;
+const LAST_DELIMITER: u8 = 0x02;
+
+/// The header of the aes128gcm content coding, RFC 8188 section 2.1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EceHeader {
+    salt: [u8; ECE_SALT_LEN],
+    rs: u32,
+    keyid: Vec<u8>,
+}
+
+impl EceHeader {
+    /// Create a header with record size `rs` and key identifier `keyid`.
+    pub fn new(salt: [u8; ECE_SALT_LEN], rs: u32, keyid: &[u8]) -> Result<Self, Error> {
+        if rs < ECE_MIN_RECORD_SIZE {
+            return Err(ApiMisuse::RecordSizeTooSmall { rs }.into());
+        }
+        if keyid.len() > usize::from(u8::MAX) {
+            return Err(ApiMisuse::KeyIdTooLong { len: keyid.len() }.into());
+        }
+        Ok(Self {
+            salt,
+            rs,
+            keyid: keyid.to_vec(),
+        })
+    }
+
+    /// Parse the header at the start of `content` and return it with the records that
+    /// follow.
+    pub fn parse(content: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (salt, rest) = content
+            .split_first_chunk::<ECE_SALT_LEN>()
+            .ok_or(Error::InvalidRecord)?;
+        let (rs, rest) = rest.split_first_chunk::<4>().ok_or(Error::InvalidRecord)?;
+        let (len, rest) = rest.split_first().ok_or(Error::InvalidRecord)?;
+        if rest.len() < usize::from(*len) {
+            return Err(Error::InvalidRecord);
+        }
+        let (keyid, records) = rest.split_at(usize::from(*len));
+
+        let rs = u32::from_be_bytes(*rs);
+        if rs < ECE_MIN_RECORD_SIZE {
+            return Err(Error::InvalidRecord);
+        }
+        let header = Self {
+            salt: *salt,
+            rs,
+            keyid: keyid.to_vec(),
+        };
+        Ok((header, records))
+    }
+
+    /// Encode the header.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(ECE_SALT_LEN + 5 + self.keyid.len());
+        out.extend_from_slice(&self.salt);
+        out.extend_from_slice(&self.rs.to_be_bytes());
+        out.push(self.keyid.len() as u8);
+        out.extend_from_slice(&self.keyid);
+        out
+    }
+
+    /// Derive the content-encryption key and the `Iv` from the input keying material.
+    pub fn keys(&self, ikm: &[u8]) -> ([u8; 16], Iv) {
+        let hkdf = Hkdf::<Sha256>::new(Some(&self.salt), ikm);
+        let mut key = [0u8; 16];
+        let mut iv = [0u8; 12];
+        hkdf.expand(b"Content-Encoding: aes128gcm\0", &mut key)
+            .expect("16 bytes is a valid HKDF-SHA256 length");
+        hkdf.expand(b"Content-Encoding: nonce\0", &mut iv)
+            .expect("12 bytes is a valid HKDF-SHA256 length");
+        (key, Iv::new(iv))
+    }
+
+    /// Return the salt used to derive the key and `Iv`.
+    pub fn salt(&self) -> &[u8; ECE_SALT_LEN] {
+        &self.salt
+    }
+
+    /// Return the record size, including the delimiter, padding and tag.
+    pub fn rs(&self) -> u32 {
+        self.rs
+    }
+
+    /// Return the key identifier.
+    pub fn keyid(&self) -> &[u8] {
+        &self.keyid
+    }
+}
+
+/// Encrypts a body into aes128gcm records.
+///
+/// The nonce of record `seq` is the `Iv` XOR `seq`.  Every record but the last is
+/// padded to exactly `rs` bytes and ends in the 0x01 delimiter; the last ends in 0x02.
+#[derive(Clone)]
+pub struct EceEncoder {
+    aead: AeadKey,
+    sequence: NonceSequence,
+    rs: u32,
+    finished: bool,
+}
+
+impl EceEncoder {
+    /// Create an encoder from a content-encryption key and `Iv`.
+    pub fn new(key: [u8; 16], iv: Iv, rs: u32) -> Result<Self, Error> {
+        if rs < ECE_MIN_RECORD_SIZE {
+            return Err(ApiMisuse::RecordSizeTooSmall { rs }.into());
+        }
+        Ok(Self {
+            aead: AeadKey::new(AeadCipher::Aes128Gcm, &key)?,
+            sequence: NonceSequence::new(iv),
+            rs,
+            finished: false,
+        })
+    }
+
+    /// Create an encoder for the records following `header`.
+    pub fn from_ikm(ikm: &[u8], header: &EceHeader) -> Self {
+        let (key, iv) = header.keys(ikm);
+        Self::new(key, iv, header.rs).expect("header has a valid record size")
+    }
+
+    /// The most plaintext bytes a record can carry.
+    pub fn max_data_len(&self) -> usize {
+        self.rs as usize - TAG_LEN - 1
+    }
+
+    /// Encrypt one record carrying `data`.
+    pub fn seal_record(&mut self, data: &[u8], last: bool) -> Result<Vec<u8>, Error> {
+        if self.finished {
+            return Err(ApiMisuse::RecordAfterLast.into());
+        }
+        if data.len() > self.max_data_len() {
+            return Err(ApiMisuse::RecordTooLong {
+                len: data.len(),
+                maximum: self.max_data_len(),
+            }
+            .into());
+        }
+
+        let mut record = Vec::with_capacity(self.rs as usize);
+        record.extend_from_slice(data);
+        if last {
+            record.push(LAST_DELIMITER);
+        } else {
+            record.push(DELIMITER);
+            record.resize(self.rs as usize - TAG_LEN, 0);
+        }
+        let nonce = self.sequence.next_nonce()?;
+        let tag = self.aead.seal(&nonce, &[], &mut record);
+        record.extend_from_slice(&tag);
+        self.finished = last;
+        Ok(record)
+    }
+
+    /// Encrypt all of `body` into records of the configured size.
+    pub fn encode(&mut self, body: &[u8]) -> Result<Vec<u8>, Error> {
+        let max = self.max_data_len();
+        let mut out = Vec::new();
+        let mut chunks = body.chunks(max).peekable();
+        if chunks.peek().is_none() {
+            return self.seal_record(&[], true);
+        }
+        while let Some(chunk) = chunks.next() {
+            out.extend(self.seal_record(chunk, chunks.peek().is_none())?);
+        }
+        Ok(out)
+    }
+}
+
+/// Decrypts aes128gcm records.
+///
+/// A record that arrives out of order fails to decrypt, and a body that ends without
+/// the last-record delimiter is reported as [`Error::Truncated`].
+#[derive(Clone)]
+pub struct EceDecoder {
+    aead: AeadKey,
+    sequence: NonceSequence,
+    rs: u32,
+    finished: bool,
+}
+
+impl EceDecoder {
+    /// Create a decoder from a content-encryption key and `Iv`.
+    pub fn new(key: [u8; 16], iv: Iv, rs: u32) -> Result<Self, Error> {
+        if rs < ECE_MIN_RECORD_SIZE {
+            return Err(ApiMisuse::RecordSizeTooSmall { rs }.into());
+        }
+        Ok(Self {
+            aead: AeadKey::new(AeadCipher::Aes128Gcm, &key)?,
+            sequence: NonceSequence::new(iv),
+            rs,
+            finished: false,
+        })
+    }
+
+    /// Create a decoder for the records following `header`.
+    pub fn from_ikm(ikm: &[u8], header: &EceHeader) -> Self {
+        let (key, iv) = header.keys(ikm);
+        Self::new(key, iv, header.rs).expect("header has a valid record size")
+    }
+
+    /// Decrypt the next record, returning its data and whether it is the last record.
+    pub fn open_record(&mut self, record: &[u8]) -> Result<(Vec<u8>, bool), Error> {
+        if self.finished || record.len() > self.rs as usize || record.len() <= TAG_LEN {
+            return Err(Error::InvalidRecord);
+        }
+
+        let (ciphertext, tag) = record.split_at(record.len() - TAG_LEN);
+        let mut data = ciphertext.to_vec();
+        let nonce = self.sequence.next_nonce()?;
+        self.aead.open(&nonce, &[], &mut data, tag)?;
+
+        let end = data
+            .iter()
+            .rposition(|b| *b != 0)
+            .ok_or(Error::InvalidRecord)?;
+        let last = match data[end] {
+            LAST_DELIMITER => true,
+            DELIMITER if record.len() == self.rs as usize => false,
+            DELIMITER => return Err(Error::Truncated),
+            _ => return Err(Error::InvalidRecord),
+        };
+        data.truncate(end);
+        self.finished = last;
+        Ok((data, last))
+    }
+
+    /// Decrypt every record in `records`, which must end with the last record.
+    pub fn decode(&mut self, records: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::with_capacity(records.len());
+        for record in records.chunks(self.rs as usize) {
+            out.extend(self.open_record(record)?.0);
+        }
+        if self.finished {
+            Ok(out)
+        } else {
+            Err(Error::Truncated)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use hex_literal::hex;
+
+    // RFC 8188 section 3.1
+    const SINGLE_RECORD: [u8; 53] = hex!(
+        "23506cc6d16db65bf7bbf3a8f78c679b0000100000"
+        "f8d015b9bdaa160044b902916a9a19bbe231908bdadcc101d4f0fe972f138638"
+    );
+
+    // RFC 8188 section 3.2
+    const MULTIPLE_RECORDS_IKM: [u8; 16] = hex!("04edd954fc549672ce45b5463296d3d5");
+    const MULTIPLE_RECORDS: [u8; 73] = hex!(
+        "b8d0a45a2358cca4e704df638b7faa5800000019026131"
+        "ce1bc721cff827be03aa746628bf1ca3baa4722458c40f2a05"
+        "d45be48fa8503dd3c7239d4e114284a60cf74ac2d622a4bfb8"
+    );
+
+    // RFC 8291 section 5
+    const WEB_PUSH_IKM: [u8; 32] =
+        hex!("4b895831bfcbd05c427aad16843c7cd772a0498a94dba90ecb359476c5d8cab8");
+    const WEB_PUSH: [u8; 144] = hex!(
+        "0c6bfaadad67958803092d454676f39700001000"
+        "4104fe33f4ab0dea71914db55823f73b54948f41306d920732dbb9a59a53286482"
+        "200e597a7b7bc260ba1c227998580992e93973002f3012a28ae8f06bbb78e5ec0f"
+        "f297de5b429bba7153d3a4ae0caa091fd425f3b4b5414add8ab37a19c1bbb05cf5"
+        "cb5b2a2e0562d558635641ec52812c6c8ff42e95ccb86be7cd"
+    );
+
+    #[test]
+    fn rfc8188_single_record() {
+        let (header, records) = EceHeader::parse(&SINGLE_RECORD).unwrap();
+        assert_eq!(header.rs(), 4096);
+        assert_eq!(header.keyid(), b"");
+        assert_eq!(header.encode(), SINGLE_RECORD[..21]);
+
+        // The CEK and NONCE listed in the RFC
+        let key = hex!("ff09e2cad07ea1fb1c643878b5b4a31f");
+        let iv = Iv::new(hex!("05cb3c82421128b23c19e23c"));
+        let mut encoder = EceEncoder::new(key, iv.clone(), 4096).unwrap();
+        assert_eq!(
+            encoder.seal_record(b"I am the walrus", true).unwrap(),
+            records
+        );
+
+        let mut decoder = EceDecoder::new(key, iv, 4096).unwrap();
+        assert_eq!(decoder.decode(records).unwrap(), b"I am the walrus");
+    }
+
+    #[test]
+    fn rfc8188_multiple_records() {
+        let (header, records) = EceHeader::parse(&MULTIPLE_RECORDS).unwrap();
+        assert_eq!(header.rs(), 25);
+        assert_eq!(header.keyid(), b"a1");
+        assert_eq!(
+            EceHeader::new(*header.salt(), 25, b"a1").unwrap().encode(),
+            MULTIPLE_RECORDS[..23]
+        );
+
+        let mut encoder = EceEncoder::from_ikm(&MULTIPLE_RECORDS_IKM, &header);
+        let mut sealed = encoder.seal_record(b"I am th", false).unwrap();
+        sealed.extend(encoder.seal_record(b"e walrus", true).unwrap());
+        assert_eq!(sealed, records);
+
+        let mut decoder = EceDecoder::from_ikm(&MULTIPLE_RECORDS_IKM, &header);
+        assert_eq!(decoder.decode(records).unwrap(), b"I am the walrus");
+    }
+
+    #[test]
+    fn rfc8291_web_push() {
+        let (header, records) = EceHeader::parse(&WEB_PUSH).unwrap();
+        assert_eq!(header.rs(), 4096);
+        assert_eq!(header.keyid().len(), 65);
+
+        let (key, iv) = header.keys(&WEB_PUSH_IKM);
+        assert_eq!(key, hex!("a088555b4e0c45dcb65cdf4288a2f14e"));
+        assert_eq!(iv.as_ref(), hex!("e21ffde6495727913faa7a0d"));
+
+        let plaintext = b"When I grow up, I want to be a watermelon";
+        let mut encoder = EceEncoder::from_ikm(&WEB_PUSH_IKM, &header);
+        assert_eq!(encoder.encode(plaintext).unwrap(), records);
+        let mut decoder = EceDecoder::from_ikm(&WEB_PUSH_IKM, &header);
+        assert_eq!(decoder.decode(records).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn truncated_and_reordered() {
+        let (header, records) = EceHeader::parse(&MULTIPLE_RECORDS).unwrap();
+        let decoder = EceDecoder::from_ikm(&MULTIPLE_RECORDS_IKM, &header);
+
+        assert!(matches!(
+            decoder.clone().decode(&records[..25]),
+            Err(Error::Truncated)
+        ));
+        // cut inside a record
+        assert!(matches!(
+            decoder.clone().decode(&records[..45]),
+            Err(Error::DecryptError)
+        ));
+
+        let mut reordered = records[25..].to_vec();
+        reordered.extend_from_slice(&records[..25]);
+        assert!(matches!(
+            decoder.clone().decode(&reordered),
+            Err(Error::DecryptError)
+        ));
+
+        let mut extended = records.to_vec();
+        extended.extend_from_slice(&records[..25]);
+        assert!(matches!(
+            decoder.clone().decode(&extended),
+            Err(Error::InvalidRecord)
+        ));
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let header = EceHeader::new([7; ECE_SALT_LEN], 20, b"").unwrap();
+        for (len, sealed_len) in [(0, 17), (1, 18), (3, 20), (6, 40), (7, 58)] {
+            let body = (0..len).collect::<Vec<u8>>();
+            let records = EceEncoder::from_ikm(b"ikm", &header).encode(&body).unwrap();
+            assert_eq!(records.len(), sealed_len);
+            let decoded = EceDecoder::from_ikm(b"ikm", &header)
+                .decode(&records)
+                .unwrap();
+            assert_eq!(decoded, body);
+        }
+
+        let mut encoder = EceEncoder::from_ikm(b"ikm", &header);
+        assert!(matches!(
+            encoder.seal_record(&[0; 4], false),
+            Err(Error::Api(ApiMisuse::RecordTooLong { len: 4, maximum: 3 }))
+        ));
+        encoder.seal_record(&[], true).unwrap();
+        assert!(matches!(
+            encoder.seal_record(&[], true),
+            Err(Error::Api(ApiMisuse::RecordAfterLast))
+        ));
+        assert!(EceHeader::new([0; ECE_SALT_LEN], 17, b"").is_err());
+    }
+}
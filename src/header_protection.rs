@@ -0,0 +1,121 @@
+use aes::cipher::{BlockEncrypt, KeyInit, KeyIvInit, StreamCipher, StreamCipherSeek};
+use aes::{Aes128, Aes256};
+use chacha20::ChaCha20;
+
+use crate::{ApiMisuse, Error};
+
+/// Length of the ciphertext sample a mask is computed from.
+pub const SAMPLE_LEN: usize = 16;
+
+/// Length of the mask, enough for a QUIC header byte and a 4-byte packet number.
+pub const MASK_LEN: usize = 5;
+
+/// A key for QUIC header protection (RFC 9001 section 5.4) and DTLS 1.3 record number
+/// encryption (RFC 9147 section 4.2.3).
+///
+/// Both derive a mask from a 16-byte ciphertext sample, with AES-ECB or with ChaCha20
+/// keyed by the sample.
+#[derive(Clone)]
+pub enum HeaderProtectionKey {
+    Aes128(Box<Aes128>),
+    Aes256(Box<Aes256>),
+    ChaCha20([u8; 32]),
+}
+
+impl HeaderProtectionKey {
+    /// Create an AES key from 16 or 32 bytes.
+    pub fn aes(key: &[u8]) -> Result<Self, Error> {
+        match key.len() {
+            16 => Ok(Self::Aes128(Box::new(
+                Aes128::new_from_slice(key).expect("16 bytes"),
+            ))),
+            32 => Ok(Self::Aes256(Box::new(
+                Aes256::new_from_slice(key).expect("32 bytes"),
+            ))),
+            actual => Err(ApiMisuse::KeyLengthUnsupported {
+                supported: &[16, 32],
+                actual,
+            }
+            .into()),
+        }
+    }
+
+    /// Create a ChaCha20 key.
+    pub fn chacha20(key: [u8; 32]) -> Self {
+        Self::ChaCha20(key)
+    }
+
+    /// Compute the mask for a ciphertext `sample`.
+    pub fn mask(&self, sample: &[u8; SAMPLE_LEN]) -> [u8; MASK_LEN] {
+        let mut block = (*sample).into();
+        match self {
+            Self::Aes128(aes) => aes.encrypt_block(&mut block),
+            Self::Aes256(aes) => aes.encrypt_block(&mut block),
+            Self::ChaCha20(key) => {
+                let counter = u32::from_le_bytes(sample[..4].try_into().unwrap());
+                let mut chacha = ChaCha20::new(key.into(), sample[4..].into());
+                chacha.seek(u64::from(counter) * 64);
+                block = Default::default();
+                chacha.apply_keystream(&mut block[..MASK_LEN]);
+            }
+        }
+        block[..MASK_LEN].try_into().unwrap()
+    }
+
+    /// Compute the mask from the first [`SAMPLE_LEN`] bytes of `ciphertext`.
+    pub fn mask_from(&self, ciphertext: &[u8]) -> Result<[u8; MASK_LEN], Error> {
+        let Some(sample) = ciphertext.first_chunk::<SAMPLE_LEN>() else {
+            return Err(Error::MessageTooShort {
+                expected: SAMPLE_LEN,
+                actual: ciphertext.len(),
+            });
+        };
+        Ok(self.mask(sample))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use hex_literal::hex;
+
+    // FIPS-197 appendix C.1
+    #[test]
+    fn aes_ecb() {
+        let key = HeaderProtectionKey::aes(&hex!("000102030405060708090a0b0c0d0e0f")).unwrap();
+        assert_eq!(
+            key.mask(&hex!("00112233445566778899aabbccddeeff")),
+            hex!("69c4e0d86a")
+        );
+    }
+
+    // RFC 9001 appendix A.2
+    #[test]
+    fn quic_aes_mask() {
+        let key = HeaderProtectionKey::aes(&hex!("9f50449e04a0e810283a1e9933adedd2")).unwrap();
+        assert_eq!(
+            key.mask(&hex!("d1b1c98dd7689fb8ec11d242b123dc9b")),
+            hex!("437b9aec36")
+        );
+    }
+
+    // RFC 9001 appendix A.5
+    #[test]
+    fn quic_chacha20_mask() {
+        let key = HeaderProtectionKey::chacha20(hex!(
+            "25a282b9e82f06f21f488917a4fc8f1b73573685608597d0efcb076b0ab7a7a4"
+        ));
+        assert_eq!(
+            key.mask(&hex!("5e5cd55c41f69080575d7999c25a5bfb")),
+            hex!("aefefe7d03")
+        );
+    }
+
+    #[test]
+    fn short_sample() {
+        let key = HeaderProtectionKey::chacha20([0; 32]);
+        assert!(key.mask_from(&[0; 15]).is_err());
+        assert!(HeaderProtectionKey::aes(&[0; 24]).is_err());
+    }
+}
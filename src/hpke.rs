@@ -0,0 +1,111 @@
+use crate::{Error, Iv, NONCE_LEN, Nonce};
+
+/// The nonce state of an HPKE encryption context, RFC 9180 section 5.2.
+///
+/// `ComputeNonce(seq)` is `base_nonce` XOR `I2OSP(seq, Nn)`, which is [`Nonce::new`].
+/// `N` is the AEAD's `Nn`; every AEAD registered for HPKE uses 12.
+#[derive(Clone)]
+pub struct HpkeNonceContext<const N: usize = NONCE_LEN> {
+    base_nonce: Iv<N>,
+    seq: u64,
+}
+
+impl<const N: usize> HpkeNonceContext<N> {
+    /// Create a context from the `base_nonce` produced by the key schedule.
+    pub fn new(base_nonce: Iv<N>) -> Self {
+        Self { base_nonce, seq: 0 }
+    }
+
+    /// Return the nonce for the current sequence number and increment it.
+    ///
+    /// RFC 9180 allows sequence numbers below 2^(8*Nn) - 1.  For `Nn` of 8 or more this
+    /// is bounded by the `u64` counter, so [`Error::MessageLimitReached`] is returned
+    /// once the sequence number reaches `u64::MAX`.
+    pub fn next_nonce(&mut self) -> Result<Nonce<N>, Error> {
+        let next = self.seq.checked_add(1).ok_or(Error::MessageLimitReached)?;
+        let nonce = self.compute_nonce(self.seq);
+        self.seq = next;
+        Ok(nonce)
+    }
+
+    /// `ComputeNonce(seq)` from RFC 9180.
+    pub fn compute_nonce(&self, seq: u64) -> Nonce<N> {
+        Nonce::new(&self.base_nonce, seq)
+    }
+
+    /// Return the current sequence number.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use aes_gcm::aead::{Aead, Payload};
+    use aes_gcm::{Aes128Gcm, KeyInit};
+    use hex_literal::hex;
+
+    // RFC 9180 A.1.1, DHKEM(X25519, HKDF-SHA256), HKDF-SHA256, AES-128-GCM
+    const KEY: [u8; 16] = hex!("4531685d41d65f03dc48f6b8302c05b0");
+    const BASE_NONCE: [u8; 12] = hex!("56d890e5accaaf011cff4b7d");
+    const PT: [u8; 29] = hex!("4265617574792069732074727574682c20747275746820626561757479");
+
+    #[test]
+    fn rfc9180_encryptions() {
+        let mut context = HpkeNonceContext::new(Iv::new(BASE_NONCE));
+        let aead = Aes128Gcm::new(&KEY.into());
+        let expected: [&[u8]; 2] = [
+            &hex!(
+                "f938558b5d72f1a23810b4be2ab4f84331acc02fc97babc53a52ae8218a355a9"
+                "6d8770ac83d07bea87e13c512a"
+            ),
+            &hex!(
+                "af2d7e9ac9ae7e270f46ba1f975be53c09f8d875bdc8535458c2494e8a6eab25"
+                "1c03d0c22a56b8ca42c2063b84"
+            ),
+        ];
+        for (seq, ct) in expected.iter().enumerate() {
+            let nonce = context.next_nonce().unwrap();
+            let aad = format!("Count-{seq}");
+            let sealed = aead
+                .encrypt(
+                    nonce.as_bytes().into(),
+                    Payload {
+                        msg: &PT,
+                        aad: aad.as_bytes(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(sealed, *ct);
+        }
+        assert_eq!(context.seq(), 2);
+
+        for (seq, nonce) in [
+            (0, hex!("56d890e5accaaf011cff4b7d")),
+            (1, hex!("56d890e5accaaf011cff4b7c")),
+            (2, hex!("56d890e5accaaf011cff4b7f")),
+            (4, hex!("56d890e5accaaf011cff4b79")),
+            (255, hex!("56d890e5accaaf011cff4b82")),
+            (256, hex!("56d890e5accaaf011cff4a7d")),
+        ] {
+            assert_eq!(context.compute_nonce(seq).to_array(), nonce);
+        }
+    }
+
+    #[test]
+    fn message_limit() {
+        let mut context = HpkeNonceContext::new(Iv::new(BASE_NONCE));
+        context.seq = u64::MAX - 1;
+        assert_eq!(
+            context.next_nonce().unwrap().to_array(),
+            hex!("56d890e5533550fee300b483")
+        );
+        assert!(matches!(
+            context.next_nonce(),
+            Err(Error::MessageLimitReached)
+        ));
+        assert_eq!(context.seq(), u64::MAX);
+    }
+}
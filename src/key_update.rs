@@ -0,0 +1,383 @@
+use crate::quic::QuicVersion;
+use crate::tls13::hkdf_expand_label;
+use crate::{Error, HashAlgorithm, Iv, NONCE_LEN, Nonce, NonceSequence};
+use zeroize::Zeroize;
+
+/// The protocol whose key update labels a [`TrafficKeyChain`] follows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyUpdateProtocol {
+    /// TLS 1.3 KeyUpdate, RFC 8446 section 7.2.
+    Tls13,
+    /// QUIC key update, RFC 9001 section 6.
+    Quic(QuicVersion),
+}
+
+impl KeyUpdateProtocol {
+    fn update_label(self) -> &'static [u8] {
+        match self {
+            Self::Tls13 => b"traffic upd",
+            Self::Quic(version) => version.labels().ku,
+        }
+    }
+
+    fn key_label(self) -> &'static [u8] {
+        match self {
+            Self::Tls13 => b"key",
+            Self::Quic(version) => version.labels().key,
+        }
+    }
+
+    fn limit(self) -> u64 {
+        match self {
+            Self::Tls13 => NonceSequence::<NONCE_LEN>::TLS_LIMIT,
+            Self::Quic(_) => NonceSequence::<NONCE_LEN>::QUIC_LIMIT,
+        }
+    }
+}
+
+/// A chain of traffic secrets linked by key updates.
+///
+/// Each generation has its own `Iv`.  With TLS 1.3 each generation also has its own
+/// nonce counter, which starts at zero; with QUIC the packet number carries on across
+/// updates, as packet numbers never go back within a space.  After an update the
+/// previous generation's secret and `Iv` are kept so that a receiver can still derive
+/// the key and nonce for packets sent before the peer saw the update, until
+/// [`Self::discard_previous`] is called at the end of the grace window.
+#[derive(Clone)]
+pub struct TrafficKeyChain<const N: usize = NONCE_LEN> {
+    protocol: KeyUpdateProtocol,
+    hash: HashAlgorithm,
+    secret: [u8; 48],
+    generation: u64,
+    sequence: NonceSequence<N>,
+    previous: Option<([u8; 48], Iv<N>)>,
+}
+
+impl<const N: usize> TrafficKeyChain<N> {
+    /// Start a chain at generation zero from a traffic `secret`.
+    pub fn new(
+        protocol: KeyUpdateProtocol,
+        hash: HashAlgorithm,
+        secret: &[u8],
+    ) -> Result<Self, Error> {
+        let iv = Self::derive_iv(protocol, hash, secret)?;
+        let mut buf = [0u8; 48];
+        buf[..secret.len()].copy_from_slice(secret);
+        Ok(Self {
+            protocol,
+            hash,
+            secret: buf,
+            generation: 0,
+            sequence: NonceSequence::with_limit(iv, protocol.limit()),
+            previous: None,
+        })
+    }
+
+    /// Move to the next generation.
+    ///
+    /// Derives the next traffic secret and its `Iv`, resets the nonce counter for TLS
+    /// 1.3 and keeps the current secret and `Iv` as the previous generation.  The
+    /// generation before that is wiped.
+    pub fn update(&mut self) {
+        let len = self.hash.output_len();
+        let mut next = [0u8; 48];
+        hkdf_expand_label(
+            self.hash,
+            &self.secret[..len],
+            b"tls13 ",
+            self.protocol.update_label(),
+            &[],
+            &mut next[..len],
+        )
+        .expect("secret length checked in new");
+        let iv = Self::derive_iv(self.protocol, self.hash, &next[..len])
+            .expect("secret length checked in new");
+
+        let next_seq = match self.protocol {
+            KeyUpdateProtocol::Tls13 => 0,
+            KeyUpdateProtocol::Quic(_) => self.sequence.next_seq(),
+        };
+        let sequence = NonceSequence::continue_from(iv, next_seq, self.protocol.limit());
+        let previous = core::mem::replace(&mut self.sequence, sequence);
+        self.discard_previous();
+        self.previous = Some((self.secret, previous.iv().clone()));
+        self.secret = next;
+        next.zeroize();
+        self.generation += 1;
+    }
+
+    /// Return the nonce for the next sequence number of the current generation.
+    pub fn next_nonce(&mut self) -> Result<Nonce<N>, Error> {
+        self.sequence.next_nonce()
+    }
+
+    /// Return the current generation, starting from zero.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Return the QUIC key phase bit of the current generation.
+    pub fn key_phase(&self) -> bool {
+        self.generation & 1 == 1
+    }
+
+    /// Return the current traffic secret.
+    pub fn secret(&self) -> &[u8] {
+        &self.secret[..self.hash.output_len()]
+    }
+
+    /// Return the nonce counter of the current generation.
+    pub fn sequence(&self) -> &NonceSequence<N> {
+        &self.sequence
+    }
+
+    /// Return the `Iv` of the current generation.
+    pub fn iv(&self) -> &Iv<N> {
+        self.sequence.iv()
+    }
+
+    /// Return the `Iv` of the previous generation, if it has not been discarded.
+    pub fn previous_iv(&self) -> Option<&Iv<N>> {
+        self.previous.as_ref().map(|(_, iv)| iv)
+    }
+
+    /// Return the `Iv` for a received packet with the given key phase bit.
+    ///
+    /// This is the current `Iv` if the bit matches, otherwise the previous one.  A
+    /// mismatching bit with no previous generation means the peer has started a key
+    /// update and `None` is returned.
+    pub fn iv_for_key_phase(&self, key_phase: bool) -> Option<&Iv<N>> {
+        if key_phase == self.key_phase() {
+            return Some(self.iv());
+        }
+        self.previous_iv()
+    }
+
+    /// Derive the write key for a received packet with the given key phase bit.
+    ///
+    /// This pairs with [`Self::iv_for_key_phase`] and returns `None` in the same cases.
+    pub fn write_key_for_key_phase<const K: usize>(&self, key_phase: bool) -> Option<[u8; K]> {
+        if key_phase == self.key_phase() {
+            return Some(self.write_key());
+        }
+        let (secret, _) = self.previous.as_ref()?;
+        Some(self.derive_key(&secret[..self.hash.output_len()]))
+    }
+
+    /// Drop the previous generation once the grace window for late packets has passed.
+    ///
+    /// Its secret is wiped.
+    pub fn discard_previous(&mut self) {
+        if let Some((secret, _)) = &mut self.previous {
+            secret.zeroize();
+        }
+        self.previous = None;
+    }
+
+    /// Derive the write key of the current generation.
+    pub fn write_key<const K: usize>(&self) -> [u8; K] {
+        self.derive_key(self.secret())
+    }
+
+    fn derive_key<const K: usize>(&self, secret: &[u8]) -> [u8; K] {
+        let mut key = [0u8; K];
+        hkdf_expand_label(
+            self.hash,
+            secret,
+            b"tls13 ",
+            self.protocol.key_label(),
+            &[],
+            &mut key,
+        )
+        .expect("secret length checked in new");
+        key
+    }
+
+    fn derive_iv(
+        protocol: KeyUpdateProtocol,
+        hash: HashAlgorithm,
+        secret: &[u8],
+    ) -> Result<Iv<N>, Error> {
+        match protocol {
+            KeyUpdateProtocol::Tls13 => Iv::from_tls13_secret(hash, secret),
+            KeyUpdateProtocol::Quic(version) => Iv::from_quic_secret(version, hash, secret),
+        }
+    }
+}
+
+impl<const N: usize> Drop for TrafficKeyChain<N> {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+        self.discard_previous();
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::aead::{AeadCipher, AeadKey};
+    use crate::tls13_write_key;
+    use hex_literal::hex;
+    use hkdf::hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    // RFC 9001 appendix A.5
+    #[test]
+    fn quic_ku() {
+        let secret = hex!("9ac312a7f877468ebe69422748ad00a15443f18203a07d6060f688f30f21632b");
+        let mut chain = TrafficKeyChain::<12>::new(
+            KeyUpdateProtocol::Quic(QuicVersion::V1),
+            HashAlgorithm::Sha256,
+            &secret,
+        )
+        .unwrap();
+
+        assert_eq!(
+            chain.write_key::<32>(),
+            hex!("c6d98ff3441c3fe1b2182094f69caa2ed4b716b65488960a7a984979fb23e1c8")
+        );
+        assert_eq!(chain.iv().as_ref(), &hex!("e0459b3474bdd0e44a41c144"));
+        assert!(!chain.key_phase());
+
+        chain.update();
+        assert_eq!(
+            chain.secret(),
+            &hex!("1223504755036d556342ee9361d253421a826c9ecdf3c7148684b36b714881f9")
+        );
+        assert!(chain.key_phase());
+        assert_eq!(chain.generation(), 1);
+        assert_eq!(
+            chain.previous_iv().unwrap().as_ref(),
+            &hex!("e0459b3474bdd0e44a41c144")
+        );
+    }
+
+    #[test]
+    fn tls13_traffic_upd() {
+        let secret = hex!("9e40646ce79a7f9dc05af8889bce6552875afa0b06df0087f792ebb7c17504a5");
+        let mut chain =
+            TrafficKeyChain::<12>::new(KeyUpdateProtocol::Tls13, HashAlgorithm::Sha256, &secret)
+                .unwrap();
+
+        // HKDF-Expand for one block is HMAC(secret, info || 0x01)
+        let mut mac = Hmac::<Sha256>::new_from_slice(&secret).unwrap();
+        mac.update(&hex!("0020"));
+        mac.update(b"\x11tls13 traffic upd\x00\x01");
+        let next: [u8; 32] = mac.finalize().into_bytes().into();
+
+        chain.next_nonce().unwrap();
+        chain.next_nonce().unwrap();
+        chain.update();
+
+        assert_eq!(chain.secret(), &next);
+        assert_eq!(chain.sequence().next_seq(), 0);
+        assert_eq!(
+            chain.iv().as_ref(),
+            Iv::<12>::from_tls13_secret(HashAlgorithm::Sha256, &next)
+                .unwrap()
+                .as_ref()
+        );
+        assert_eq!(
+            chain.write_key::<16>(),
+            tls13_write_key::<16>(HashAlgorithm::Sha256, &next).unwrap()
+        );
+        assert_eq!(chain.next_nonce().unwrap(), Nonce::new(chain.iv(), 0));
+    }
+
+    #[test]
+    fn quic_update_keeps_packet_number() {
+        let mut chain = TrafficKeyChain::<12>::new(
+            KeyUpdateProtocol::Quic(QuicVersion::V1),
+            HashAlgorithm::Sha256,
+            &[3; 32],
+        )
+        .unwrap();
+        let first = chain.iv().clone();
+        assert_eq!(chain.next_nonce().unwrap(), Nonce::new(&first, 0));
+        assert_eq!(chain.next_nonce().unwrap(), Nonce::new(&first, 1));
+
+        chain.update();
+        assert_eq!(chain.sequence().next_seq(), 2);
+        assert_eq!(chain.next_nonce().unwrap(), Nonce::new(chain.iv(), 2));
+        chain.update();
+        assert_eq!(chain.next_nonce().unwrap(), Nonce::new(chain.iv(), 3));
+        assert_eq!(chain.sequence().limit(), NonceSequence::<12>::QUIC_LIMIT);
+    }
+
+    #[test]
+    fn grace_window() {
+        let mut chain = TrafficKeyChain::<12>::new(
+            KeyUpdateProtocol::Quic(QuicVersion::V2),
+            HashAlgorithm::Sha384,
+            &[7; 48],
+        )
+        .unwrap();
+        let first = chain.iv().clone();
+
+        assert_eq!(
+            chain.iv_for_key_phase(false).unwrap().as_ref(),
+            first.as_ref()
+        );
+        assert!(chain.iv_for_key_phase(true).is_none());
+
+        chain.update();
+        let second = chain.iv().clone();
+        assert_eq!(
+            chain.iv_for_key_phase(true).unwrap().as_ref(),
+            second.as_ref()
+        );
+        assert_eq!(
+            chain.iv_for_key_phase(false).unwrap().as_ref(),
+            first.as_ref()
+        );
+
+        chain.discard_previous();
+        assert!(chain.iv_for_key_phase(false).is_none());
+
+        chain.update();
+        chain.update();
+        assert_eq!(chain.generation(), 3);
+        assert!(chain.iv_for_key_phase(false).is_some());
+    }
+
+    #[test]
+    fn open_previous_key_phase() {
+        let mut chain = TrafficKeyChain::<12>::new(
+            KeyUpdateProtocol::Quic(QuicVersion::V1),
+            HashAlgorithm::Sha256,
+            &[5; 32],
+        )
+        .unwrap();
+        let nonce = chain.next_nonce().unwrap();
+        let mut payload = *b"sent before the update";
+        let tag = AeadKey::new(AeadCipher::Aes128Gcm, &chain.write_key::<16>())
+            .unwrap()
+            .seal(&nonce, b"header", &mut payload);
+
+        chain.update();
+        assert_ne!(
+            chain.write_key_for_key_phase::<16>(true),
+            chain.write_key_for_key_phase::<16>(false)
+        );
+        let key = chain.write_key_for_key_phase::<16>(false).unwrap();
+        let iv = chain.iv_for_key_phase(false).unwrap();
+        AeadKey::new(AeadCipher::Aes128Gcm, &key)
+            .unwrap()
+            .open(&Nonce::new(iv, 0), b"header", &mut payload, &tag)
+            .unwrap();
+        assert_eq!(&payload, b"sent before the update");
+
+        chain.discard_previous();
+        assert!(chain.write_key_for_key_phase::<16>(false).is_none());
+        assert!(chain.write_key_for_key_phase::<16>(true).is_some());
+    }
+
+    #[test]
+    fn secret_length() {
+        assert!(
+            TrafficKeyChain::<12>::new(KeyUpdateProtocol::Tls13, HashAlgorithm::Sha384, &[0; 32])
+                .is_err()
+        );
+    }
+}
@@ -1,3 +1,97 @@
+mod aead;
+pub use aead::{AeadCipher, TAG_LEN};
+
+mod backend;
+pub use backend::{DefaultBackend, NativeBackend, NonceBackend, RustlsBackend};
+
+mod dtls;
+pub use dtls::{
+    DTLS_SEQ_LIMIT, DtlsEpochNonces, DtlsVersion, dtls_nonce, dtls13_record_number_mask,
+    dtls13_sn_key, reconstruct_dtls13_epoch, reconstruct_dtls13_seq,
+};
+
+mod ece;
+pub use ece::{ECE_MIN_RECORD_SIZE, ECE_SALT_LEN, EceDecoder, EceEncoder, EceHeader};
+
+mod header_protection;
+pub use header_protection::{HeaderProtectionKey, MASK_LEN, SAMPLE_LEN};
+
+mod hpke;
+pub use hpke::HpkeNonceContext;
+
+mod key_update;
+pub use key_update::{KeyUpdateProtocol, TrafficKeyChain};
+
+mod limits;
+pub use limits::{AeadAlgorithm, UsageLimits, UsageStatus, UsageTracker};
+
+mod mls;
+pub use mls::{
+    MLS_REUSE_GUARD_LEN, MlsCipherSuite, MlsGenerationKeys, MlsSenderRatchet, apply_reuse_guard,
+    remove_reuse_guard,
+};
+
+mod multipath;
+pub use multipath::MultipathNonceContext;
+
+mod noise;
+pub use noise::{NoiseCipher, NoiseCipherNonce};
+
+mod ohttp;
+pub use ohttp::{ChunkedOhttpDecoder, ChunkedOhttpEncoder};
+
+mod packet_number;
+pub use packet_number::{
+    QUIC_PN_LIMIT, TruncatedPacketNumber, decode_packet_number, encode_packet_number,
+};
+
+mod packet_protection;
+pub use packet_protection::{OpenedPacket, PacketProtection, retry_integrity_tag, verify_retry};
+
+mod pn_space;
+pub use pn_space::{EncryptionLevel, PacketNumberSpace, PacketNumberSpaces};
+
+mod quic;
+pub use quic::{QuicKeys, QuicVersion, Side, quic_initial_secret};
+
+mod reconstruct;
+pub use reconstruct::{
+    ReconstructPolicy, reconstruct, reconstruct_esp_seq, reconstruct_lorawan_fcnt,
+    reconstruct_pdcp_count, reconstruct_secoc_freshness, reconstruct_srtp_index,
+};
+
+mod replay;
+pub use replay::ReplayWindow;
+
+mod sequence;
+pub use sequence::NonceSequence;
+
+mod sframe;
+pub use sframe::{SframeCipherSuite, SframeHeader, SframeKeys};
+
+mod srtp;
+pub use srtp::{
+    SRTCP_INDEX_LIMIT, SRTP_INDEX_LIMIT, SrtpContext, encode_srtcp_index, parse_srtcp_index,
+    srtcp_nonce, srtp_nonce,
+};
+
+mod tls12;
+pub use tls12::{
+    TLS12_EXPLICIT_NONCE_LEN, Tls12Aead, Tls12Nonces, parse_explicit_nonce, write_explicit_nonce,
+};
+
+mod tls13;
+pub use tls13::{HashAlgorithm, tls13_write_key};
+
+mod wireguard;
+pub use wireguard::{
+    REJECT_AFTER_MESSAGES, REKEY_AFTER_MESSAGES, WireGuardReceiver, WireGuardSender,
+    wireguard_nonce,
+};
+
+mod xchacha;
+pub use xchacha::XCHACHA_NONCE_LEN;
+
 //---------------------------------------
 // Case 1: rustls cut-n-paste
 //---------------------------------------
@@ -6,25 +100,108 @@ pub const NONCE_LEN: usize = 12;
 #[derive(Debug)]
 #[allow(dead_code)]
 pub enum ApiMisuse {
-    IvLengthExceedsMaximum { actual: usize, maximum: usize },
-    NonceArraySizeMismatch { expected: usize, actual: usize },
+    IvLengthExceedsMaximum {
+        actual: usize,
+        maximum: usize,
+    },
+    NonceArraySizeMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    IvLengthMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    SecretLengthMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    KeyLengthMismatch {
+        expected: usize,
+        actual: usize,
+    },
+    SequenceExceedsIvWidth {
+        seq: u64,
+        iv_len: usize,
+    },
+    PathIdDoesNotFit {
+        path_id: u32,
+        iv_len: usize,
+    },
+    EpochExceedsWidth {
+        epoch: u64,
+    },
+    PacketNumberLength {
+        len: usize,
+    },
+    PacketNumberUnencodable {
+        full_pn: u64,
+        largest_acked: Option<u64>,
+    },
+    RecordSizeTooSmall {
+        rs: u32,
+    },
+    RecordTooLong {
+        len: usize,
+        maximum: usize,
+    },
+    RecordAfterLast,
+    KeyIdTooLong {
+        len: usize,
+    },
+    ReplayWindowWidth {
+        width: u64,
+    },
+    SrtcpIndexExceedsWidth {
+        srtcp_index: u32,
+    },
+    KeyLengthUnsupported {
+        supported: &'static [usize],
+        actual: usize,
+    },
+    SequenceNumberLength {
+        len: usize,
+    },
+    TruncatedWidth {
+        bits: u32,
+    },
+    PacketNumberExceedsLimit {
+        pn: u64,
+    },
+    ChunkLengthZero,
+    RecordBlocksZero,
+    ConnectionIdLength {
+        len: usize,
+    },
 }
 
-/// A write or read IV.
+/// A write or read IV whose length is only known at runtime.
 #[derive(Default, Clone)]
-pub struct Iv {
+pub struct DynIv {
     buf: [u8; Self::MAX_LEN],
     used: usize,
 }
 
-pub(crate) fn put_u64(v: u64, bytes: &mut [u8]) {
-    let bytes: &mut [u8; 8] = (&mut bytes[..8]).try_into().unwrap();
-    *bytes = u64::to_be_bytes(v);
-}
-
 #[derive(Debug)]
 pub enum Error {
     Api(ApiMisuse),
+    SequenceExhausted { limit: u64 },
+    AeadLimitReached,
+    MessageTooShort { expected: usize, actual: usize },
+    InvalidPacket,
+    DecryptError,
+    PathIdExceedsMaximum { path_id: u64, maximum: u32 },
+    UnknownPath { path_id: u32 },
+    KeysUnavailable { level: EncryptionLevel },
+    KeysDiscarded { level: EncryptionLevel },
+    MessageLimitReached,
+    InvalidRecord,
+    Truncated,
+    GenerationUnavailable { generation: u32 },
+    GenerationTooFarAhead { generation: u32, current: u32 },
+    Replay { seq: u64 },
+    OutsideWindow { seq: u64 },
+    NonMinimalEncoding,
 }
 
 impl From<ApiMisuse> for Error {
@@ -33,8 +210,8 @@ impl From<ApiMisuse> for Error {
     }
 }
 
-impl Iv {
-    /// Create a new `Iv` from a byte slice.
+impl DynIv {
+    /// Create a new `DynIv` from a byte slice.
     ///
     /// Returns an error if the length of `value` exceeds [`Self::MAX_LEN`].
     pub fn new(value: &[u8]) -> Result<Self, Error> {
@@ -60,68 +237,107 @@ impl Iv {
     }
 
     /// Maximum supported IV length.
-    pub const MAX_LEN: usize = 16;
+    ///
+    /// This fits the 24-byte XChaCha20-Poly1305 and 32-byte AEGIS-256 nonces.
+    pub const MAX_LEN: usize = 32;
 }
 
-impl From<[u8; NONCE_LEN]> for Iv {
+impl From<[u8; NONCE_LEN]> for DynIv {
     fn from(bytes: [u8; NONCE_LEN]) -> Self {
         Self::new(&bytes).expect("NONCE_LEN is within MAX_LEN")
     }
 }
 
-impl AsRef<[u8]> for Iv {
+impl AsRef<[u8]> for DynIv {
     fn as_ref(&self) -> &[u8] {
         &self.buf[..self.used]
     }
 }
 
-/// A nonce.  This is unique for all messages on a connection.
-pub struct Nonce {
-    buf: [u8; Iv::MAX_LEN],
+/// A nonce whose length is only known at runtime.
+///
+/// This is unique for all messages on a connection.
+pub struct DynNonce {
+    buf: [u8; DynIv::MAX_LEN],
     len: usize,
 }
 
-impl Nonce {
-    /// Combine an `Iv` and sequence number to produce a unique nonce.
+impl DynNonce {
+    /// Combine a `DynIv` and sequence number to produce a unique nonce.
     ///
     /// This is `iv ^ seq` where `seq` is encoded as a big-endian integer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `seq` does not fit in the IV width, see [`Self::try_new`].
     #[inline]
-    pub fn new(iv: &Iv, seq: u64) -> Self {
-        Self::new_inner(None, iv, seq)
+    pub fn new(iv: &DynIv, seq: u64) -> Self {
+        Self::try_new(iv, seq).expect("sequence number exceeds IV width")
+    }
+
+    /// Combine a `DynIv` and sequence number to produce a unique nonce.
+    ///
+    /// Returns an error if the IV is shorter than 8 bytes and `seq` does not fit in it,
+    /// as the high bytes would otherwise be dropped and distinct sequence numbers could
+    /// produce the same nonce.
+    #[inline]
+    pub fn try_new(iv: &DynIv, seq: u64) -> Result<Self, Error> {
+        Self::try_new_inner(None, iv, seq)
     }
 
     /// Creates a unique nonce based on the multipath `path_id`, the `iv` and packet number `pn`.
     ///
     /// The nonce is computed as the XOR between the `iv` and the big-endian integer formed
     /// by concatenating `path_id` (or 0) and `pn`.
-    pub fn quic(path_id: Option<u32>, iv: &Iv, pn: u64) -> Self {
-        Self::new_inner(path_id, iv, pn)
+    ///
+    /// # Panics
+    ///
+    /// Panics if `path_id` or `pn` do not fit in the IV width, see [`Self::try_quic`].
+    pub fn quic(path_id: Option<u32>, iv: &DynIv, pn: u64) -> Self {
+        Self::try_quic(path_id, iv, pn).expect("path ID or packet number exceeds IV width")
     }
 
-    /// Creates a unique nonce based on the iv and sequence number seq.
+    /// Creates a unique nonce based on the multipath `path_id`, the `iv` and packet number `pn`.
+    ///
+    /// Returns an error if a `path_id` is given and the IV is shorter than 12 bytes, or if
+    /// `pn` does not fit in the IV width.
+    pub fn try_quic(path_id: Option<u32>, iv: &DynIv, pn: u64) -> Result<Self, Error> {
+        Self::try_new_inner(path_id, iv, pn)
+    }
+
+    /// Check that `path_id` and `seq` fit in the IV before building the nonce.
     #[inline]
-    fn new_inner(path_id: Option<u32>, iv: &Iv, seq: u64) -> Self {
+    fn try_new_inner(path_id: Option<u32>, iv: &DynIv, seq: u64) -> Result<Self, Error> {
+        Self::check(path_id, iv, seq)?;
+        Ok(Self::new_inner(path_id, iv, seq))
+    }
+
+    /// Check that `path_id` and `seq` fit in the IV.
+    #[inline]
+    fn check(path_id: Option<u32>, iv: &DynIv, seq: u64) -> Result<(), Error> {
         let iv_len = iv.len();
-        let mut buf = [0u8; Iv::MAX_LEN];
-
-        if iv_len >= 8 {
-            put_u64(seq, &mut buf[iv_len - 8..iv_len]);
-            if let Some(path_id) = path_id {
-                if iv_len >= 12 {
-                    buf[iv_len - 12..iv_len - 8].copy_from_slice(&path_id.to_be_bytes());
-                }
-            }
-        } else {
-            let seq_bytes = seq.to_be_bytes();
-            buf[..iv_len].copy_from_slice(&seq_bytes[8 - iv_len..]);
+
+        if iv_len < 8 && seq >> (8 * iv_len) != 0 {
+            return Err(ApiMisuse::SequenceExceedsIvWidth { seq, iv_len }.into());
         }
 
-        buf[..iv_len]
-            .iter_mut()
-            .zip(iv.as_ref())
-            .for_each(|(s, iv)| *s ^= *iv);
+        if let Some(path_id) = path_id
+            && iv_len < 12
+        {
+            return Err(ApiMisuse::PathIdDoesNotFit { path_id, iv_len }.into());
+        }
 
-        Self { buf, len: iv_len }
+        Ok(())
+    }
+
+    /// Creates a unique nonce based on the iv and sequence number seq.
+    ///
+    /// This goes through the [`DefaultBackend`].
+    #[inline]
+    fn new_inner(path_id: Option<u32>, iv: &DynIv, seq: u64) -> Self {
+        let mut buf = [0u8; DynIv::MAX_LEN];
+        DefaultBackend::xor_be_slice(iv.as_ref(), quic_word(path_id, seq), &mut buf[..iv.len()]);
+        Self { buf, len: iv.len() }
     }
 
     /// Convert to a fixed-size array of length `N`.
@@ -156,27 +372,192 @@ impl Nonce {
 }
 
 //---------------------------------------
-// Case 2 - Using crypto_bigint U128
+// Case 2 - Using crypto_bigint U128 / U256
 //---------------------------------------
 
-use crypto_bigint::Encoding;
+use crypto_bigint::{Encoding, U128, U256};
+
+use backend::quic_word;
 
 pub struct CryptoBigInt;
 
 impl CryptoBigInt {
     pub fn seq_nonce(iv_bytes: &[u8; 12], seq_id: u64) -> [u8; 12] {
-        let mut u128_iv: [u8; 16] = [0; 16];
-        u128_iv[4..16].copy_from_slice(iv_bytes);
-        let iv_u128 = crypto_bigint::U128::from_be_bytes(u128_iv);
-        let seq_no_u128 = crypto_bigint::U128::from_u64(seq_id);
-        let nonce_u128 = iv_u128.wrapping_xor(&seq_no_u128);
-        let b: [u8; 16] = nonce_u128.to_be_bytes();
-        [
-            b[4], b[5], b[6], b[7], b[8], b[9], b[10], b[11], b[12], b[13], b[14], b[15],
-        ]
+        Self::nonce(&Iv::new(*iv_bytes), seq_id).to_array()
+    }
+
+    /// Produce the same nonce as [`DynNonce::try_new`].
+    pub fn dyn_nonce(iv: &DynIv, seq: u64) -> Result<DynNonce, Error> {
+        Self::dyn_quic(None, iv, seq)
+    }
+
+    /// Produce the same nonce as [`DynNonce::try_quic`].
+    pub fn dyn_quic(path_id: Option<u32>, iv: &DynIv, pn: u64) -> Result<DynNonce, Error> {
+        DynNonce::check(path_id, iv, pn)?;
+        let mut buf = [0u8; DynIv::MAX_LEN];
+        Self::xor_be_slice(iv.as_ref(), quic_word(path_id, pn), &mut buf[..iv.len()]);
+        Ok(DynNonce { buf, len: iv.len() })
+    }
+}
+
+impl NonceBackend for CryptoBigInt {
+    #[inline]
+    fn xor_be<const N: usize>(iv: &[u8; N], word: u128) -> [u8; N] {
+        let mut buf = [0u8; N];
+        Self::xor_be_slice(iv, word, &mut buf);
+        buf
+    }
+
+    /// Write `iv ^ word` into `out`, with `word` aligned to the end of the IV.
+    ///
+    /// Uses a `U128` for IVs of up to 16 bytes and a `U256` up to 32 bytes.  Longer
+    /// IVs only change in their last 16 bytes, which are XORed as a `U128`.
+    #[inline]
+    fn xor_be_slice(iv: &[u8], word: u128, out: &mut [u8]) {
+        let len = iv.len();
+        if len > 32 {
+            out.copy_from_slice(iv);
+            Self::xor_be_slice(&iv[len - 16..], word, &mut out[len - 16..]);
+        } else if len <= 16 {
+            let mut wide = [0u8; 16];
+            wide[16 - len..].copy_from_slice(iv);
+            let xored = U128::from_be_bytes(wide).wrapping_xor(&U128::from_u128(word));
+            out.copy_from_slice(&xored.to_be_bytes()[16 - len..]);
+        } else {
+            let mut wide = [0u8; 32];
+            wide[32 - len..].copy_from_slice(iv);
+            let xored = U256::from_be_bytes(wide).wrapping_xor(&U256::from_u128(word));
+            out.copy_from_slice(&xored.to_be_bytes()[32 - len..]);
+        }
+    }
+}
+
+//---------------------------------------
+// Case 3: const generic Iv<N> / Nonce<N>
+//---------------------------------------
+
+/// A write or read IV of exactly `N` bytes.
+#[derive(Clone)]
+pub struct Iv<const N: usize = NONCE_LEN>([u8; N]);
+
+impl<const N: usize> Iv<N> {
+    /// Create a new `Iv` from a byte array.
+    pub const fn new(value: [u8; N]) -> Self {
+        Self(value)
+    }
+
+    /// The IV length.
+    pub const LEN: usize = N;
+}
+
+impl<const N: usize> From<[u8; N]> for Iv<N> {
+    fn from(bytes: [u8; N]) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for Iv<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<const N: usize> From<Iv<N>> for DynIv {
+    fn from(iv: Iv<N>) -> Self {
+        const { assert!(N <= DynIv::MAX_LEN, "IV length exceeds DynIv::MAX_LEN") };
+        Self::new(&iv.0).expect("N is within MAX_LEN")
+    }
+}
+
+impl<const N: usize> TryFrom<&DynIv> for Iv<N> {
+    type Error = Error;
+
+    fn try_from(iv: &DynIv) -> Result<Self, Error> {
+        let bytes = iv
+            .as_ref()
+            .try_into()
+            .map_err(|_| ApiMisuse::IvLengthMismatch {
+                expected: N,
+                actual: iv.len(),
+            })?;
+        Ok(Self(bytes))
+    }
+}
+
+/// A nonce of exactly `N` bytes.  This is unique for all messages on a connection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Nonce<const N: usize = NONCE_LEN>([u8; N]);
+
+impl<const N: usize> Nonce<N> {
+    /// Combine an `Iv` and sequence number to produce a unique nonce.
+    ///
+    /// This is `iv ^ seq` where `seq` is encoded as a big-endian integer.  `N` must be
+    /// at least 8 so that no part of `seq` is dropped, which is checked at compile time.
+    ///
+    /// This goes through the [`DefaultBackend`].
+    #[inline]
+    pub fn new(iv: &Iv<N>, seq: u64) -> Self {
+        DefaultBackend::nonce(iv, seq)
+    }
+
+    /// Creates a unique nonce based on the multipath `path_id`, the `iv` and packet number `pn`.
+    ///
+    /// The nonce is computed as the XOR between the `iv` and the big-endian integer formed
+    /// by concatenating `path_id` (or 0) and `pn`.  `N` must be at least 12 so that the
+    /// `path_id` is not dropped, which is checked at compile time.
+    ///
+    /// This goes through the [`DefaultBackend`].
+    #[inline]
+    pub fn quic(path_id: Option<u32>, iv: &Iv<N>, pn: u64) -> Self {
+        DefaultBackend::quic(path_id, iv, pn)
+    }
+
+    /// Convert to a fixed-size array.
+    pub const fn to_array(&self) -> [u8; N] {
+        self.0
     }
+
+    /// Return the nonce value.
+    pub const fn as_bytes(&self) -> &[u8; N] {
+        &self.0
+    }
+
+    /// The nonce length.
+    pub const LEN: usize = N;
 }
 
+impl<const N: usize> From<Nonce<N>> for [u8; N] {
+    fn from(nonce: Nonce<N>) -> Self {
+        nonce.0
+    }
+}
+
+impl<const N: usize> From<Nonce<N>> for DynNonce {
+    fn from(nonce: Nonce<N>) -> Self {
+        const { assert!(N <= DynIv::MAX_LEN, "nonce length exceeds DynIv::MAX_LEN") };
+        let mut buf = [0u8; DynIv::MAX_LEN];
+        buf[..N].copy_from_slice(&nonce.0);
+        Self { buf, len: N }
+    }
+}
+
+impl<const N: usize> TryFrom<&DynNonce> for Nonce<N> {
+    type Error = Error;
+
+    fn try_from(nonce: &DynNonce) -> Result<Self, Error> {
+        nonce.to_array().map(Self)
+    }
+}
+
+/// XOR `other` into `buf`.
+#[inline]
+pub(crate) fn xor(buf: &mut [u8], other: &[u8]) {
+    buf.iter_mut().zip(other).for_each(|(b, o)| *b ^= *o);
+}
+
+#[cfg(test)]
+mod test_util;
+
 #[cfg(test)]
 mod test {
 
@@ -187,8 +568,8 @@ mod test {
     fn compat() {
         let iv_bytes: [u8; 12] = hex!("6fac81d4f2c3bebe02b8b375");
 
-        let iv = Iv::new(&iv_bytes).unwrap();
-        let rustls_nonce_1 = Nonce::new(&iv, 1);
+        let iv = DynIv::new(&iv_bytes).unwrap();
+        let rustls_nonce_1 = DynNonce::new(&iv, 1);
 
         let crypto_bigint_nonce_1 = CryptoBigInt::seq_nonce(&iv_bytes, 1);
 
@@ -196,4 +577,144 @@ mod test {
 
         assert_eq!(&crypto_bigint_nonce_1, &hex!("6fac81d4f2c3bebe02b8b374"));
     }
+
+    #[test]
+    fn const_generic_compat() {
+        let iv_bytes: [u8; 12] = hex!("6fac81d4f2c3bebe02b8b375");
+
+        let dyn_nonce = DynNonce::new(&DynIv::new(&iv_bytes).unwrap(), 0x0102_0304_0506_0708);
+        let nonce = Nonce::new(&Iv::new(iv_bytes), 0x0102_0304_0506_0708);
+        assert_eq!(dyn_nonce.as_bytes(), nonce.as_bytes());
+
+        let dyn_nonce = DynNonce::quic(Some(7), &DynIv::new(&iv_bytes).unwrap(), 1);
+        let nonce = Nonce::quic(Some(7), &Iv::new(iv_bytes), 1);
+        assert_eq!(dyn_nonce.as_bytes(), nonce.as_bytes());
+        assert_eq!(nonce.to_array(), hex!("6fac81d3f2c3bebe02b8b374"));
+    }
+
+    #[test]
+    fn dyn_conversions() {
+        let iv = Iv::new(hex!("6fac81d4f2c3bebe02b8b375"));
+        let dyn_iv = DynIv::from(iv.clone());
+        assert_eq!(Iv::<12>::try_from(&dyn_iv).unwrap().as_ref(), iv.as_ref());
+        assert!(matches!(
+            Iv::<16>::try_from(&dyn_iv),
+            Err(Error::Api(ApiMisuse::IvLengthMismatch {
+                expected: 16,
+                actual: 12
+            }))
+        ));
+
+        let nonce = Nonce::new(&iv, 42);
+        let dyn_nonce = DynNonce::from(nonce.clone());
+        assert_eq!(Nonce::<12>::try_from(&dyn_nonce).unwrap(), nonce);
+        assert!(Nonce::<16>::try_from(&dyn_nonce).is_err());
+    }
+
+    #[test]
+    fn long_nonces() {
+        let iv_bytes: [u8; 32] =
+            hex!("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+
+        let iv = DynIv::new(&iv_bytes).unwrap();
+        let dyn_nonce = DynNonce::new(&iv, 0x0102);
+        assert_eq!(
+            dyn_nonce.as_bytes(),
+            &hex!("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1f1d")
+        );
+        assert_eq!(
+            Nonce::new(&Iv::new(iv_bytes), 0x0102).as_bytes(),
+            dyn_nonce.as_bytes()
+        );
+
+        let iv = DynIv::new(&iv_bytes[..24]).unwrap();
+        let dyn_nonce = DynNonce::new(&iv, 0x0102);
+        assert_eq!(
+            dyn_nonce.to_array::<24>().unwrap(),
+            hex!("000102030405060708090a0b0c0d0e0f1011121314151715")
+        );
+    }
+
+    #[test]
+    fn crypto_bigint_all_lengths() {
+        let iv_bytes: [u8; 32] =
+            hex!("6fac81d4f2c3bebe02b8b3756fac81d4f2c3bebe02b8b3756fac81d4f2c3bebe");
+
+        for len in 1..=DynIv::MAX_LEN {
+            let iv = DynIv::new(&iv_bytes[..len]).unwrap();
+            let seq = if len < 8 { 0xa5 } else { 0x0102_0304_0506_0708 };
+            assert_eq!(
+                CryptoBigInt::dyn_nonce(&iv, seq).unwrap().as_bytes(),
+                DynNonce::new(&iv, seq).as_bytes()
+            );
+            if len >= 12 {
+                assert_eq!(
+                    CryptoBigInt::dyn_quic(Some(0xdead_beef), &iv, seq)
+                        .unwrap()
+                        .as_bytes(),
+                    DynNonce::quic(Some(0xdead_beef), &iv, seq).as_bytes()
+                );
+            }
+        }
+
+        assert!(CryptoBigInt::dyn_nonce(&DynIv::new(&iv_bytes[..4]).unwrap(), u64::MAX).is_err());
+        assert!(CryptoBigInt::dyn_quic(Some(1), &DynIv::new(&iv_bytes[..8]).unwrap(), 1).is_err());
+    }
+
+    #[test]
+    fn crypto_bigint_const_generic() {
+        let iv = Iv::new(hex!("6fac81d4f2c3bebe02b8b375"));
+        assert_eq!(CryptoBigInt::nonce(&iv, 9), Nonce::new(&iv, 9));
+        assert_eq!(
+            CryptoBigInt::quic(Some(3), &iv, 9),
+            Nonce::quic(Some(3), &iv, 9)
+        );
+
+        let iv = Iv::new(hex!("404142434445464748494a4b4c4d4e4f5051525354555657"));
+        assert_eq!(
+            CryptoBigInt::nonce(&iv, u64::MAX),
+            Nonce::new(&iv, u64::MAX)
+        );
+        assert_eq!(
+            CryptoBigInt::quic(Some(u32::MAX), &iv, 9),
+            Nonce::quic(Some(u32::MAX), &iv, 9)
+        );
+    }
+
+    #[test]
+    fn short_iv_rejects_truncated_seq() {
+        let iv = DynIv::new(&hex!("a0a1a2a3")).unwrap();
+
+        let nonce = DynNonce::try_new(&iv, 0xffff_ffff).unwrap();
+        assert_eq!(nonce.as_bytes(), &hex!("5f5e5d5c"));
+
+        assert!(matches!(
+            DynNonce::try_new(&iv, 0x1_0000_0000),
+            Err(Error::Api(ApiMisuse::SequenceExceedsIvWidth {
+                seq: 0x1_0000_0000,
+                iv_len: 4
+            }))
+        ));
+    }
+
+    #[test]
+    fn short_iv_rejects_path_id() {
+        let iv = DynIv::new(&hex!("000102030405060708090a")).unwrap();
+
+        assert!(DynNonce::try_quic(None, &iv, 1).is_ok());
+        assert!(matches!(
+            DynNonce::try_quic(Some(1), &iv, 1),
+            Err(Error::Api(ApiMisuse::PathIdDoesNotFit {
+                path_id: 1,
+                iv_len: 11
+            }))
+        ));
+    }
+
+    #[test]
+    #[should_panic]
+    fn new_panics_on_truncated_seq() {
+        let iv = DynIv::new(&hex!("a0a1a2a3")).unwrap();
+        DynNonce::new(&iv, u64::MAX);
+    }
 }
@@ -0,0 +1,341 @@
+use crate::{ApiMisuse, Error};
+
+/// An AEAD with published usage limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AeadAlgorithm {
+    Aes128Gcm,
+    Aes256Gcm,
+    ChaCha20Poly1305,
+    Aes128Ccm,
+}
+
+/// Per-key limits on records protected and on failed decryptions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UsageLimits {
+    /// Records that may be encrypted under one key.
+    pub confidentiality: u64,
+    /// Forgery attempts that may be made against one key.
+    pub integrity: u64,
+    /// Whether encrypted records count against `integrity` as well, as in the AES-CCM
+    /// bound of draft-irtf-cfrg-aead-limits.
+    pub integrity_counts_encryptions: bool,
+}
+
+impl UsageLimits {
+    /// The limits from RFC 9001 section 6.6.
+    pub const fn rfc9001(aead: AeadAlgorithm) -> Self {
+        match aead {
+            AeadAlgorithm::Aes128Gcm | AeadAlgorithm::Aes256Gcm => Self {
+                confidentiality: 1 << 23,
+                integrity: 1 << 52,
+                integrity_counts_encryptions: false,
+            },
+            AeadAlgorithm::ChaCha20Poly1305 => Self {
+                confidentiality: u64::MAX,
+                integrity: 1 << 36,
+                integrity_counts_encryptions: false,
+            },
+            // 2^21.5; only packets failing authentication count against integrity
+            AeadAlgorithm::Aes128Ccm => Self {
+                confidentiality: 2_965_820,
+                integrity: 2_965_820,
+                integrity_counts_encryptions: false,
+            },
+        }
+    }
+
+    /// The limits from draft-irtf-cfrg-aead-limits for records of up to `record_blocks`
+    /// 16-byte blocks.
+    ///
+    /// The adversary's advantage is kept below 2^-`confidentiality_log2` for
+    /// confidentiality and 2^-`integrity_log2` for integrity.  For AES-CCM the integrity
+    /// bound covers forgery attempts and encrypted records together.
+    ///
+    /// Returns [`ApiMisuse::RecordBlocksZero`] if `record_blocks` is zero.
+    pub fn for_advantage(
+        aead: AeadAlgorithm,
+        record_blocks: u64,
+        confidentiality_log2: u32,
+        integrity_log2: u32,
+    ) -> Result<Self, Error> {
+        if record_blocks == 0 {
+            return Err(ApiMisuse::RecordBlocksZero.into());
+        }
+        let l = record_blocks as f64;
+        let ca = f64::from(confidentiality_log2);
+        let ia = f64::from(integrity_log2);
+        let (confidentiality, integrity) = match aead {
+            // CA <= (s + q + 1)^2 / 2^129, IA <= 2 * (v * (L + 1)) / 2^128
+            AeadAlgorithm::Aes128Gcm | AeadAlgorithm::Aes256Gcm => (
+                (((129.0 - ca) / 2.0).exp2() - 1.0) / (l + 1.0),
+                (127.0 - ia).exp2() / (l + 1.0),
+            ),
+            // IA <= v * (L + 1) / 2^103
+            AeadAlgorithm::ChaCha20Poly1305 => (f64::INFINITY, (103.0 - ia).exp2() / (l + 1.0)),
+            // CA <= (2L * q)^2 / 2^128, IA <= v / 2^128 + (2L * (v + q))^2 / 2^128
+            AeadAlgorithm::Aes128Ccm => (
+                ((128.0 - ca) / 2.0).exp2() / (2.0 * l),
+                ((128.0 - ia) / 2.0).exp2() / (2.0 * l),
+            ),
+        };
+
+        // float to int casts saturate
+        Ok(Self {
+            confidentiality: confidentiality as u64,
+            integrity: integrity as u64,
+            integrity_counts_encryptions: aead == AeadAlgorithm::Aes128Ccm,
+        })
+    }
+}
+
+/// The state of a key reported by [`UsageTracker`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsageStatus {
+    /// The key may keep being used.
+    Ok,
+    /// The update threshold was reached and a key update should be started now.
+    UpdateKey,
+    /// The integrity limit was reached and the connection must be closed, with
+    /// AEAD_LIMIT_REACHED in QUIC.
+    LimitReached,
+}
+
+/// Records how much a single key has been used against its [`UsageLimits`].
+#[derive(Clone, Debug)]
+pub struct UsageTracker {
+    aead: AeadAlgorithm,
+    limits: UsageLimits,
+    update_threshold: u64,
+    records: u64,
+    bytes: u64,
+    failures: u64,
+}
+
+impl UsageTracker {
+    /// Track a key with the RFC 9001 limits for `aead`.
+    pub fn new(aead: AeadAlgorithm) -> Self {
+        Self::with_limits(aead, UsageLimits::rfc9001(aead))
+    }
+
+    /// Track a key with custom limits.
+    ///
+    /// The update threshold defaults to the confidentiality limit.
+    pub fn with_limits(aead: AeadAlgorithm, limits: UsageLimits) -> Self {
+        Self {
+            aead,
+            limits,
+            update_threshold: limits.confidentiality,
+            records: 0,
+            bytes: 0,
+            failures: 0,
+        }
+    }
+
+    /// Signal [`UsageStatus::UpdateKey`] once `threshold` records have been encrypted.
+    ///
+    /// The threshold is capped at the confidentiality limit.
+    pub fn with_update_threshold(mut self, threshold: u64) -> Self {
+        self.update_threshold = threshold.min(self.limits.confidentiality);
+        self
+    }
+
+    /// Record that a record of `len` bytes is about to be encrypted.
+    ///
+    /// Returns an error without counting the record if the confidentiality limit has
+    /// been reached.
+    pub fn record_encryption(&mut self, len: usize) -> Result<UsageStatus, Error> {
+        if self.records >= self.limits.confidentiality
+            || self.integrity_used() >= self.limits.integrity
+        {
+            return Err(Error::AeadLimitReached);
+        }
+        self.records += 1;
+        self.bytes = self.bytes.saturating_add(len as u64);
+        Ok(self.status())
+    }
+
+    /// Record a failed decryption.
+    pub fn record_decryption_failure(&mut self) -> UsageStatus {
+        self.failures = self.failures.saturating_add(1);
+        self.status()
+    }
+
+    /// Return the current state of the key.
+    pub fn status(&self) -> UsageStatus {
+        if self.integrity_used() >= self.limits.integrity {
+            UsageStatus::LimitReached
+        } else if self.records >= self.update_threshold {
+            UsageStatus::UpdateKey
+        } else {
+            UsageStatus::Ok
+        }
+    }
+
+    /// Return the number of records that can still be encrypted.
+    pub fn remaining_encryptions(&self) -> u64 {
+        self.limits.confidentiality.saturating_sub(self.records)
+    }
+
+    /// Return the number of failed decryptions that can still be tolerated.
+    pub fn remaining_failures(&self) -> u64 {
+        self.limits.integrity.saturating_sub(self.integrity_used())
+    }
+
+    /// Return the number of records encrypted.
+    pub fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Return the number of bytes encrypted.
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Return the number of failed decryptions.
+    pub fn failures(&self) -> u64 {
+        self.failures
+    }
+
+    /// Return the AEAD of the tracked key.
+    pub fn aead(&self) -> AeadAlgorithm {
+        self.aead
+    }
+
+    /// Return the limits in use.
+    pub fn limits(&self) -> &UsageLimits {
+        &self.limits
+    }
+
+    /// The AES-CCM bound of [`UsageLimits::for_advantage`] counts encryptions as well as
+    /// forgery attempts.
+    fn integrity_used(&self) -> u64 {
+        if self.limits.integrity_counts_encryptions {
+            self.failures.saturating_add(self.records)
+        } else {
+            self.failures
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn rfc9001_limits() {
+        let gcm = UsageLimits::rfc9001(AeadAlgorithm::Aes256Gcm);
+        assert_eq!(gcm.confidentiality, 8_388_608);
+        assert_eq!(gcm.integrity, 4_503_599_627_370_496);
+
+        let chacha = UsageLimits::rfc9001(AeadAlgorithm::ChaCha20Poly1305);
+        assert_eq!(chacha.integrity, 68_719_476_736);
+
+        let ccm = UsageLimits::rfc9001(AeadAlgorithm::Aes128Ccm);
+        assert_eq!(ccm.confidentiality, 2f64.powf(21.5) as u64);
+    }
+
+    #[test]
+    fn advantage_limits() {
+        // 2^14 byte records are 2^10 blocks
+        let gcm = UsageLimits::for_advantage(AeadAlgorithm::Aes128Gcm, 1 << 10, 57, 57).unwrap();
+        assert_eq!(gcm.confidentiality, ((1u64 << 36) - 1) / 1025);
+        assert_eq!(gcm.integrity, (2f64.powi(70) / 1025.0) as u64);
+
+        let chacha =
+            UsageLimits::for_advantage(AeadAlgorithm::ChaCha20Poly1305, 1 << 10, 57, 57).unwrap();
+        assert_eq!(chacha.confidentiality, u64::MAX);
+        assert_eq!(chacha.integrity, (1u64 << 46) / 1025);
+
+        let ccm = UsageLimits::for_advantage(AeadAlgorithm::Aes128Ccm, 1 << 10, 58, 58).unwrap();
+        assert_eq!(ccm.confidentiality, 1 << 24);
+        assert_eq!(ccm.integrity, 1 << 24);
+
+        let stricter =
+            UsageLimits::for_advantage(AeadAlgorithm::Aes128Gcm, 1 << 10, 60, 60).unwrap();
+        assert!(stricter.confidentiality < gcm.confidentiality);
+        assert!(stricter.integrity < gcm.integrity);
+
+        assert!(matches!(
+            UsageLimits::for_advantage(AeadAlgorithm::Aes128Gcm, 0, 57, 57),
+            Err(Error::Api(ApiMisuse::RecordBlocksZero))
+        ));
+    }
+
+    #[test]
+    fn update_then_refuse() {
+        let limits = UsageLimits {
+            confidentiality: 4,
+            integrity: 2,
+            integrity_counts_encryptions: false,
+        };
+        let mut tracker =
+            UsageTracker::with_limits(AeadAlgorithm::Aes128Gcm, limits).with_update_threshold(3);
+
+        assert_eq!(tracker.record_encryption(100).unwrap(), UsageStatus::Ok);
+        assert_eq!(tracker.record_encryption(100).unwrap(), UsageStatus::Ok);
+        assert_eq!(
+            tracker.record_encryption(100).unwrap(),
+            UsageStatus::UpdateKey
+        );
+        assert_eq!(
+            tracker.record_encryption(100).unwrap(),
+            UsageStatus::UpdateKey
+        );
+        assert_eq!(tracker.remaining_encryptions(), 0);
+        assert!(matches!(
+            tracker.record_encryption(100),
+            Err(Error::AeadLimitReached)
+        ));
+        assert_eq!(tracker.records(), 4);
+        assert_eq!(tracker.bytes(), 400);
+
+        assert_eq!(tracker.remaining_failures(), 2);
+        assert_eq!(tracker.record_decryption_failure(), UsageStatus::UpdateKey);
+        assert_eq!(
+            tracker.record_decryption_failure(),
+            UsageStatus::LimitReached
+        );
+        assert_eq!(tracker.remaining_failures(), 0);
+    }
+
+    #[test]
+    fn ccm_counts_encryptions_for_integrity() {
+        let limits = UsageLimits {
+            confidentiality: 10,
+            integrity: 3,
+            integrity_counts_encryptions: true,
+        };
+        let mut tracker = UsageTracker::with_limits(AeadAlgorithm::Aes128Ccm, limits);
+
+        tracker.record_encryption(16).unwrap();
+        tracker.record_encryption(16).unwrap();
+        assert_eq!(tracker.remaining_failures(), 1);
+        assert_eq!(
+            tracker.record_decryption_failure(),
+            UsageStatus::LimitReached
+        );
+        assert!(tracker.record_encryption(16).is_err());
+    }
+
+    #[test]
+    fn ccm_rfc9001_counts_only_failures() {
+        let mut tracker = UsageTracker::new(AeadAlgorithm::Aes128Ccm);
+        tracker.records = 2_000_000;
+        assert_eq!(tracker.status(), UsageStatus::Ok);
+        assert_eq!(tracker.record_decryption_failure(), UsageStatus::Ok);
+        assert_eq!(tracker.remaining_failures(), 2_965_819);
+        tracker.record_encryption(16).unwrap();
+
+        tracker.failures = 2_965_819;
+        assert_eq!(
+            tracker.record_decryption_failure(),
+            UsageStatus::LimitReached
+        );
+        assert!(
+            UsageLimits::for_advantage(AeadAlgorithm::Aes128Ccm, 1 << 10, 58, 58)
+                .unwrap()
+                .integrity_counts_encryptions
+        );
+    }
+}
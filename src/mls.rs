@@ -0,0 +1,508 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use hkdf::Hkdf;
+use sha2::{Sha256, Sha384, Sha512};
+use zeroize::Zeroize;
+
+use crate::{ApiMisuse, Error, NONCE_LEN, Nonce};
+
+/// Length of the `reuse_guard` carried in MLS private messages.
+pub const MLS_REUSE_GUARD_LEN: usize = 4;
+
+/// The MLS cipher suites, RFC 9420 section 17.1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MlsCipherSuite {
+    X25519Aes128GcmSha256Ed25519,
+    P256Aes128GcmSha256P256,
+    X25519ChaCha20Poly1305Sha256Ed25519,
+    X448Aes256GcmSha512Ed448,
+    P521Aes256GcmSha512P521,
+    X448ChaCha20Poly1305Sha512Ed448,
+    P384Aes256GcmSha384P384,
+}
+
+impl MlsCipherSuite {
+    /// Map a cipher suite identifier to the suite.
+    pub fn from_wire(value: u16) -> Option<Self> {
+        Some(match value {
+            0x0001 => Self::X25519Aes128GcmSha256Ed25519,
+            0x0002 => Self::P256Aes128GcmSha256P256,
+            0x0003 => Self::X25519ChaCha20Poly1305Sha256Ed25519,
+            0x0004 => Self::X448Aes256GcmSha512Ed448,
+            0x0005 => Self::P521Aes256GcmSha512P521,
+            0x0006 => Self::X448ChaCha20Poly1305Sha512Ed448,
+            0x0007 => Self::P384Aes256GcmSha384P384,
+            _ => return None,
+        })
+    }
+
+    /// Return the cipher suite identifier.
+    pub fn to_wire(self) -> u16 {
+        match self {
+            Self::X25519Aes128GcmSha256Ed25519 => 0x0001,
+            Self::P256Aes128GcmSha256P256 => 0x0002,
+            Self::X25519ChaCha20Poly1305Sha256Ed25519 => 0x0003,
+            Self::X448Aes256GcmSha512Ed448 => 0x0004,
+            Self::P521Aes256GcmSha512P521 => 0x0005,
+            Self::X448ChaCha20Poly1305Sha512Ed448 => 0x0006,
+            Self::P384Aes256GcmSha384P384 => 0x0007,
+        }
+    }
+
+    /// Return `KDF.Nh`, the length of a ratchet secret.
+    pub const fn hash_len(self) -> usize {
+        match self {
+            Self::X25519Aes128GcmSha256Ed25519
+            | Self::P256Aes128GcmSha256P256
+            | Self::X25519ChaCha20Poly1305Sha256Ed25519 => 32,
+            Self::P384Aes256GcmSha384P384 => 48,
+            Self::X448Aes256GcmSha512Ed448
+            | Self::P521Aes256GcmSha512P521
+            | Self::X448ChaCha20Poly1305Sha512Ed448 => 64,
+        }
+    }
+
+    /// Return `AEAD.Nk`, the length of a message key.
+    pub const fn key_len(self) -> usize {
+        match self {
+            Self::X25519Aes128GcmSha256Ed25519 | Self::P256Aes128GcmSha256P256 => 16,
+            _ => 32,
+        }
+    }
+}
+
+/// The key and nonce of one generation of a sender ratchet.
+///
+/// Both are wiped when the value is dropped.
+#[derive(Clone)]
+pub struct MlsGenerationKeys {
+    generation: u32,
+    key: [u8; 32],
+    key_len: usize,
+    nonce: Nonce,
+}
+
+impl MlsGenerationKeys {
+    /// Return the generation these keys belong to.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Return the AEAD key, `AEAD.Nk` bytes long.
+    pub fn key(&self) -> &[u8] {
+        &self.key[..self.key_len]
+    }
+
+    /// Return the nonce before the `reuse_guard` is applied.
+    pub fn nonce(&self) -> &Nonce {
+        &self.nonce
+    }
+
+    /// Return the nonce with `reuse_guard` applied, as used to seal or open a message.
+    pub fn guarded_nonce(&self, reuse_guard: [u8; MLS_REUSE_GUARD_LEN]) -> Nonce {
+        apply_reuse_guard(&self.nonce, reuse_guard)
+    }
+}
+
+/// The key is left out so that it does not end up in logs.
+impl fmt::Debug for MlsGenerationKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MlsGenerationKeys")
+            .field("generation", &self.generation)
+            .field("key_len", &self.key_len)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for MlsGenerationKeys {
+    fn drop(&mut self) {
+        self.key.zeroize();
+        self.nonce.0.zeroize();
+    }
+}
+
+/// XOR `reuse_guard` into the first four bytes of `nonce`, RFC 9420 section 6.3.1.
+pub fn apply_reuse_guard(nonce: &Nonce, reuse_guard: [u8; MLS_REUSE_GUARD_LEN]) -> Nonce {
+    let mut buf = nonce.0;
+    crate::xor(&mut buf[..MLS_REUSE_GUARD_LEN], &reuse_guard);
+    Nonce(buf)
+}
+
+/// Undo [`apply_reuse_guard`], recovering the nonce derived from the ratchet.
+pub fn remove_reuse_guard(nonce: &Nonce, reuse_guard: [u8; MLS_REUSE_GUARD_LEN]) -> Nonce {
+    apply_reuse_guard(nonce, reuse_guard)
+}
+
+/// A sender ratchet from the MLS secret tree, RFC 9420 section 9.1.
+///
+/// Each generation `j` derives `key_j`, `nonce_j` and the next ratchet secret from
+/// `secret_j`, after which `secret_j` is forgotten.  Keys of skipped generations are
+/// retained within a bounded window so messages can be opened out of order, and every
+/// generation's keys are handed out at most once.  The ratchet secret is wiped as it
+/// moves forward and when the ratchet is dropped.
+#[derive(Clone)]
+pub struct MlsSenderRatchet {
+    suite: MlsCipherSuite,
+    secret: [u8; 64],
+    generation: u32,
+    exhausted: bool,
+    retained: BTreeMap<u32, MlsGenerationKeys>,
+    window: u32,
+    max_forward: u32,
+}
+
+impl MlsSenderRatchet {
+    /// How many past generations are retained by default.
+    pub const DEFAULT_WINDOW: u32 = 16;
+
+    /// How far ahead of the ratchet a generation may be by default.
+    pub const DEFAULT_MAX_FORWARD: u32 = 1024;
+
+    /// Create a ratchet at generation 0 from a leaf's ratchet secret.
+    pub fn new(suite: MlsCipherSuite, ratchet_secret: &[u8]) -> Result<Self, Error> {
+        Self::with_window(
+            suite,
+            ratchet_secret,
+            Self::DEFAULT_WINDOW,
+            Self::DEFAULT_MAX_FORWARD,
+        )
+    }
+
+    /// Create a ratchet which retains `window` past generations and accepts generations
+    /// up to `max_forward` ahead.
+    pub fn with_window(
+        suite: MlsCipherSuite,
+        ratchet_secret: &[u8],
+        window: u32,
+        max_forward: u32,
+    ) -> Result<Self, Error> {
+        if ratchet_secret.len() != suite.hash_len() {
+            return Err(ApiMisuse::SecretLengthMismatch {
+                expected: suite.hash_len(),
+                actual: ratchet_secret.len(),
+            }
+            .into());
+        }
+        let mut secret = [0u8; 64];
+        secret[..ratchet_secret.len()].copy_from_slice(ratchet_secret);
+        Ok(Self {
+            suite,
+            secret,
+            generation: 0,
+            exhausted: false,
+            retained: BTreeMap::new(),
+            window,
+            max_forward,
+        })
+    }
+
+    /// Return the keys of the next generation, for sending.
+    pub fn next_keys(&mut self) -> Result<MlsGenerationKeys, Error> {
+        self.advance()
+    }
+
+    /// Return the keys of `generation`, for receiving.
+    ///
+    /// Returns [`Error::GenerationUnavailable`] if the keys were already handed out or
+    /// have fallen out of the window, and [`Error::GenerationTooFarAhead`] if
+    /// `generation` is more than the configured distance ahead.
+    pub fn keys_for(&mut self, generation: u32) -> Result<MlsGenerationKeys, Error> {
+        if generation < self.generation || self.exhausted {
+            return self
+                .retained
+                .remove(&generation)
+                .ok_or(Error::GenerationUnavailable { generation });
+        }
+        if generation - self.generation > self.max_forward {
+            return Err(Error::GenerationTooFarAhead {
+                generation,
+                current: self.generation,
+            });
+        }
+
+        while self.generation < generation {
+            let skipped = self.advance()?;
+            self.retained.insert(skipped.generation, skipped);
+        }
+        let keys = self.advance()?;
+        let oldest = self.generation.saturating_sub(self.window);
+        self.retained.retain(|g, _| *g >= oldest);
+        Ok(keys)
+    }
+
+    /// Return the generation the ratchet will derive next.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Derive the current generation and move the ratchet secret forward.
+    fn advance(&mut self) -> Result<MlsGenerationKeys, Error> {
+        if self.exhausted {
+            return Err(Error::SequenceExhausted {
+                limit: u64::from(u32::MAX) + 1,
+            });
+        }
+
+        let generation = self.generation;
+        let secret = &self.secret[..self.suite.hash_len()];
+        let mut key = [0u8; 32];
+        let mut nonce = [0u8; NONCE_LEN];
+        let mut next = [0u8; 64];
+        let key_len = self.suite.key_len();
+        derive_tree_secret(self.suite, secret, b"key", generation, &mut key[..key_len]);
+        derive_tree_secret(self.suite, secret, b"nonce", generation, &mut nonce);
+        derive_tree_secret(
+            self.suite,
+            secret,
+            b"secret",
+            generation,
+            &mut next[..self.suite.hash_len()],
+        );
+
+        self.secret.zeroize();
+        self.secret = next;
+        next.zeroize();
+        match generation.checked_add(1) {
+            Some(next) => self.generation = next,
+            None => self.exhausted = true,
+        }
+        let keys = MlsGenerationKeys {
+            generation,
+            key,
+            key_len,
+            nonce: Nonce(nonce),
+        };
+        key.zeroize();
+        nonce.zeroize();
+        Ok(keys)
+    }
+}
+
+impl Drop for MlsSenderRatchet {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+    }
+}
+
+/// `DeriveTreeSecret`, which is `ExpandWithLabel(secret, label, generation, len)` from
+/// RFC 9420 section 8.
+fn derive_tree_secret(
+    suite: MlsCipherSuite,
+    secret: &[u8],
+    label: &[u8],
+    generation: u32,
+    out: &mut [u8],
+) {
+    let out_len = u16::try_from(out.len())
+        .expect("output length fits in u16")
+        .to_be_bytes();
+    // Both vectors are shorter than 64 bytes, so their varint lengths are one byte.
+    let label_len = [8 + label.len() as u8];
+    let context = generation.to_be_bytes();
+    let info: [&[u8]; 6] = [&out_len, &label_len, b"MLS 1.0 ", label, &[4], &context];
+
+    match suite.hash_len() {
+        32 => Hkdf::<Sha256>::from_prk(secret)
+            .expect("secret length checked")
+            .expand_multi_info(&info, out),
+        48 => Hkdf::<Sha384>::from_prk(secret)
+            .expect("secret length checked")
+            .expand_multi_info(&info, out),
+        _ => Hkdf::<Sha512>::from_prk(secret)
+            .expect("secret length checked")
+            .expand_multi_info(&info, out),
+    }
+    .expect("output length is within 255 * hash length");
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use hex_literal::hex;
+
+    const SECRET: [u8; 32] =
+        hex!("9e40646ce79a7f9dc05af8889bce6552875afa0b06df0087f792ebb7c17504a5");
+    const SUITE: MlsCipherSuite = MlsCipherSuite::X25519Aes128GcmSha256Ed25519;
+
+    // The expected values in these tests were computed outside this crate with HMAC
+    // from the KDFLabel of RFC 9420 section 8, not taken from the secret-tree vectors of
+    // the mls-implementations repository.
+    #[test]
+    fn derive_generations() {
+        let mut ratchet = MlsSenderRatchet::new(SUITE, &SECRET).unwrap();
+        for (generation, key, nonce) in [
+            (
+                0,
+                hex!("a60c2325b80ac44aac60b33b3293faf7"),
+                hex!("2e7f420844203832c02c4ee0"),
+            ),
+            (
+                1,
+                hex!("82a45963327625b891cd3365a3c1c4db"),
+                hex!("d134ceb535ad529f8223c5f0"),
+            ),
+            (
+                2,
+                hex!("dcc92a8337ad0bff73b55d5c6f0aa5ad"),
+                hex!("307cb70fe11c18e059f0b992"),
+            ),
+        ] {
+            let keys = ratchet.next_keys().unwrap();
+            assert_eq!(keys.generation(), generation);
+            assert_eq!(keys.key(), key);
+            assert_eq!(keys.nonce().to_array(), nonce);
+        }
+        assert_eq!(ratchet.generation(), 3);
+        assert_eq!(
+            ratchet.secret[..32],
+            hex!("f8207e9baef0181c595c1133042bd768e208301fcdef70c810cc7306327c7619")
+        );
+    }
+
+    // DeriveTreeSecret(secret, label, generation, length) for the other hash functions
+    #[test]
+    fn derive_tree_secret_vectors() {
+        let secret = (0..64).collect::<Vec<u8>>();
+
+        let mut out = [0; 32];
+        derive_tree_secret(
+            MlsCipherSuite::P384Aes256GcmSha384P384,
+            &secret[..48],
+            b"key",
+            0x01020304,
+            &mut out,
+        );
+        assert_eq!(
+            out,
+            hex!("bccfa03f39842e74155e11865bec40f2226fe1d7fc766855d31fb9a56227ca30")
+        );
+
+        let mut out = [0; 12];
+        derive_tree_secret(
+            MlsCipherSuite::P384Aes256GcmSha384P384,
+            &secret[..48],
+            b"nonce",
+            0x01020304,
+            &mut out,
+        );
+        assert_eq!(out, hex!("f9954b05a30d7cda1b4fdb50"));
+
+        let mut out = [0; 64];
+        derive_tree_secret(
+            MlsCipherSuite::X448Aes256GcmSha512Ed448,
+            &secret,
+            b"secret",
+            7,
+            &mut out,
+        );
+        assert_eq!(
+            out,
+            hex!(
+                "a15de7060fdd94d5a063693edd819b225ecbfbc5afd73096aed4e8758d044bbe"
+                "3107b9fb4628081e2b83b96f5ebc7eb40112153d1da55974af4d0fc9adae58a4"
+            )
+        );
+    }
+
+    #[test]
+    fn reuse_guard() {
+        let keys = MlsSenderRatchet::new(SUITE, &SECRET)
+            .unwrap()
+            .next_keys()
+            .unwrap();
+        let guard = hex!("01020304");
+        let guarded = keys.guarded_nonce(guard);
+
+        let mut expected = keys.nonce().to_array();
+        for (b, g) in expected.iter_mut().zip(guard) {
+            *b ^= g;
+        }
+        assert_eq!(guarded.as_bytes(), &expected);
+        assert_eq!(guarded.as_bytes()[4..], keys.nonce().as_bytes()[4..]);
+        assert_eq!(&remove_reuse_guard(&guarded, guard), keys.nonce());
+
+        let debug = format!("{keys:?}");
+        assert!(debug.contains("generation: 0"));
+        assert!(!debug.contains(&format!("{:?}", keys.key())));
+    }
+
+    fn assert_same(a: &MlsGenerationKeys, b: &MlsGenerationKeys) {
+        assert_eq!(a.generation(), b.generation());
+        assert_eq!(a.key(), b.key());
+        assert_eq!(a.nonce(), b.nonce());
+    }
+
+    #[test]
+    fn out_of_order() {
+        let mut sender = MlsSenderRatchet::new(SUITE, &SECRET).unwrap();
+        let sent = (0..6)
+            .map(|_| sender.next_keys().unwrap())
+            .collect::<Vec<_>>();
+
+        let mut receiver = MlsSenderRatchet::with_window(SUITE, &SECRET, 3, 4).unwrap();
+        assert_same(&receiver.keys_for(2).unwrap(), &sent[2]);
+        assert_same(&receiver.keys_for(0).unwrap(), &sent[0]);
+        assert!(matches!(
+            receiver.keys_for(0),
+            Err(Error::GenerationUnavailable { generation: 0 })
+        ));
+        assert!(matches!(
+            receiver.keys_for(2),
+            Err(Error::GenerationUnavailable { generation: 2 })
+        ));
+
+        // generation 1 falls out of the window once generation 5 is received
+        assert_same(&receiver.keys_for(5).unwrap(), &sent[5]);
+        assert!(matches!(
+            receiver.keys_for(1),
+            Err(Error::GenerationUnavailable { generation: 1 })
+        ));
+        assert_same(&receiver.keys_for(3).unwrap(), &sent[3]);
+        assert_same(&receiver.keys_for(4).unwrap(), &sent[4]);
+
+        assert!(matches!(
+            receiver.keys_for(11),
+            Err(Error::GenerationTooFarAhead {
+                generation: 11,
+                current: 6
+            })
+        ));
+        assert_eq!(receiver.keys_for(10).unwrap().generation(), 10);
+    }
+
+    #[test]
+    fn suites() {
+        for value in 1..=7 {
+            let suite = MlsCipherSuite::from_wire(value).unwrap();
+            assert_eq!(suite.to_wire(), value);
+            let secret = vec![0x42; suite.hash_len()];
+            let keys = MlsSenderRatchet::new(suite, &secret)
+                .unwrap()
+                .next_keys()
+                .unwrap();
+            assert_eq!(keys.key().len(), suite.key_len());
+        }
+        assert_eq!(MlsCipherSuite::from_wire(8), None);
+        assert!(matches!(
+            MlsSenderRatchet::new(MlsCipherSuite::P384Aes256GcmSha384P384, &SECRET),
+            Err(Error::Api(ApiMisuse::SecretLengthMismatch {
+                expected: 48,
+                actual: 32
+            }))
+        ));
+    }
+
+    #[test]
+    fn exhausted() {
+        let mut ratchet = MlsSenderRatchet::new(SUITE, &SECRET).unwrap();
+        ratchet.generation = u32::MAX;
+        assert_eq!(ratchet.next_keys().unwrap().generation(), u32::MAX);
+        assert!(matches!(
+            ratchet.next_keys(),
+            Err(Error::SequenceExhausted { .. })
+        ));
+        assert!(ratchet.keys_for(u32::MAX).is_err());
+    }
+}
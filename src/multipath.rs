@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+
+use crate::{Error, Iv, NONCE_LEN, Nonce, NonceSequence};
+
+/// Nonces for multipath QUIC, draft-ietf-quic-multipath.
+///
+/// Every path has its own packet number space, and the path ID is folded into the
+/// nonce as in [`Nonce::quic`].  Path IDs are allocated in increasing order and never
+/// reused, so no (path ID, packet number) pair is issued twice.
+#[derive(Clone)]
+pub struct MultipathNonceContext<const N: usize = NONCE_LEN> {
+    iv: Iv<N>,
+    next_path_id: u64,
+    max_path_id: u32,
+    paths: BTreeMap<u32, NonceSequence<N>>,
+}
+
+impl<const N: usize> MultipathNonceContext<N> {
+    /// Create the context with path 0 open, accepting path IDs up to the peer's
+    /// `max_path_id`.
+    pub fn new(iv: Iv<N>, max_path_id: u32) -> Self {
+        let mut context = Self {
+            iv,
+            next_path_id: 0,
+            max_path_id,
+            paths: BTreeMap::new(),
+        };
+        context.open_path().expect("path 0 is always allowed");
+        context
+    }
+
+    /// Raise the maximum path ID after a MAX_PATH_ID frame from the peer.
+    ///
+    /// The maximum never decreases, so a smaller value is ignored.
+    pub fn set_max_path_id(&mut self, max_path_id: u32) {
+        self.max_path_id = self.max_path_id.max(max_path_id);
+    }
+
+    /// Open the next path and return its ID.
+    ///
+    /// Returns an error if the peer's maximum path ID, or 2^32-1, has been reached.
+    pub fn open_path(&mut self) -> Result<u32, Error> {
+        let path_id = u32::try_from(self.next_path_id)
+            .ok()
+            .filter(|path_id| *path_id <= self.max_path_id)
+            .ok_or(Error::PathIdExceedsMaximum {
+                path_id: self.next_path_id,
+                maximum: self.max_path_id,
+            })?;
+
+        let iv = Iv::new(Nonce::quic(Some(path_id), &self.iv, 0).to_array());
+        self.paths.insert(
+            path_id,
+            NonceSequence::with_limit(iv, NonceSequence::<N>::QUIC_LIMIT),
+        );
+        self.next_path_id += 1;
+        Ok(path_id)
+    }
+
+    /// Retire a path.  Its ID is never handed out again.
+    pub fn retire_path(&mut self, path_id: u32) -> Result<(), Error> {
+        self.paths
+            .remove(&path_id)
+            .map(|_| ())
+            .ok_or(Error::UnknownPath { path_id })
+    }
+
+    /// Return the next packet number on `path_id` and its nonce.
+    pub fn next_nonce(&mut self, path_id: u32) -> Result<(u64, Nonce<N>), Error> {
+        let sequence = self
+            .paths
+            .get_mut(&path_id)
+            .ok_or(Error::UnknownPath { path_id })?;
+        let pn = sequence.next_seq();
+        Ok((pn, sequence.next_nonce()?))
+    }
+
+    /// Return the nonce for a packet received on `path_id` with packet number `pn`.
+    pub fn nonce_for(&self, path_id: u32, pn: u64) -> Result<Nonce<N>, Error> {
+        let sequence = self
+            .paths
+            .get(&path_id)
+            .ok_or(Error::UnknownPath { path_id })?;
+        Ok(Nonce::new(sequence.iv(), pn))
+    }
+
+    /// Return the next packet number on `path_id`, if the path is open.
+    pub fn next_pn(&self, path_id: u32) -> Option<u64> {
+        self.paths.get(&path_id).map(NonceSequence::next_seq)
+    }
+
+    /// Return the IDs of the open paths in increasing order.
+    pub fn paths(&self) -> impl Iterator<Item = u32> + '_ {
+        self.paths.keys().copied()
+    }
+
+    /// Return the peer's maximum path ID.
+    pub fn max_path_id(&self) -> u32 {
+        self.max_path_id
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use hex_literal::hex;
+
+    fn iv() -> Iv {
+        Iv::new(hex!("6fac81d4f2c3bebe02b8b375"))
+    }
+
+    #[test]
+    fn per_path_spaces() {
+        let mut context = MultipathNonceContext::new(iv(), 2);
+        assert_eq!(context.open_path().unwrap(), 1);
+
+        assert_eq!(
+            context.next_nonce(0).unwrap(),
+            (0, Nonce::quic(Some(0), &iv(), 0))
+        );
+        assert_eq!(
+            context.next_nonce(1).unwrap(),
+            (0, Nonce::quic(Some(1), &iv(), 0))
+        );
+        assert_eq!(
+            context.next_nonce(1).unwrap(),
+            (1, Nonce::quic(Some(1), &iv(), 1))
+        );
+        assert_eq!(context.next_pn(0), Some(1));
+        assert_eq!(
+            context.nonce_for(1, 7).unwrap(),
+            Nonce::quic(Some(1), &iv(), 7)
+        );
+
+        // path 0 matches single-path QUIC
+        assert_eq!(Nonce::quic(Some(0), &iv(), 0), Nonce::quic(None, &iv(), 0));
+    }
+
+    #[test]
+    fn max_path_id() {
+        let mut context = MultipathNonceContext::new(iv(), 1);
+        assert_eq!(context.open_path().unwrap(), 1);
+        assert!(matches!(
+            context.open_path(),
+            Err(Error::PathIdExceedsMaximum {
+                path_id: 2,
+                maximum: 1
+            })
+        ));
+
+        context.set_max_path_id(0);
+        assert_eq!(context.max_path_id(), 1);
+        context.set_max_path_id(3);
+        assert_eq!(context.open_path().unwrap(), 2);
+    }
+
+    #[test]
+    fn retired_paths_are_not_reused() {
+        let mut context = MultipathNonceContext::new(iv(), 10);
+        context.open_path().unwrap();
+        context.retire_path(0).unwrap();
+
+        assert!(matches!(
+            context.next_nonce(0),
+            Err(Error::UnknownPath { path_id: 0 })
+        ));
+        assert!(context.retire_path(0).is_err());
+        assert_eq!(context.open_path().unwrap(), 2);
+        assert_eq!(context.paths().collect::<Vec<_>>(), [1, 2]);
+    }
+
+    #[test]
+    fn path_id_space_exhausted() {
+        let mut context = MultipathNonceContext::new(iv(), u32::MAX);
+        context.next_path_id = u64::from(u32::MAX);
+
+        assert_eq!(context.open_path().unwrap(), u32::MAX);
+        assert!(matches!(
+            context.open_path(),
+            Err(Error::PathIdExceedsMaximum {
+                path_id: 0x1_0000_0000,
+                maximum: u32::MAX
+            })
+        ));
+        assert_eq!(
+            context.next_nonce(u32::MAX).unwrap().1,
+            Nonce::quic(Some(u32::MAX), &iv(), 0)
+        );
+    }
+}
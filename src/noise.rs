@@ -0,0 +1,154 @@
+use crate::aead::{AeadCipher, AeadKey};
+use crate::{Error, NONCE_LEN, Nonce};
+
+/// The cipher functions of the Noise Protocol Framework, section 12.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NoiseCipher {
+    ChaChaPoly,
+    AesGcm,
+}
+
+impl NoiseCipher {
+    /// Encode the counter `n` as 32 zero bits followed by `n`, little-endian for
+    /// ChaChaPoly and big-endian for AESGCM.
+    pub fn nonce(self, n: u64) -> Nonce {
+        let mut buf = [0u8; NONCE_LEN];
+        buf[4..].copy_from_slice(&match self {
+            Self::ChaChaPoly => n.to_le_bytes(),
+            Self::AesGcm => n.to_be_bytes(),
+        });
+        Nonce(buf)
+    }
+
+    /// `REKEY(k)` from section 4.2: the first 32 bytes of encrypting 32 zero bytes with
+    /// nonce 2^64-1 and empty associated data.
+    pub fn rekey(self, key: &[u8; 32]) -> [u8; 32] {
+        let aead = match self {
+            Self::ChaChaPoly => AeadCipher::ChaCha20Poly1305,
+            Self::AesGcm => AeadCipher::Aes256Gcm,
+        };
+        let mut next = [0u8; 32];
+        AeadKey::new(aead, key).expect("32-byte key").seal(
+            &self.nonce(NoiseCipherNonce::REKEY_NONCE),
+            &[],
+            &mut next,
+        );
+        next
+    }
+}
+
+/// The nonce `n` of a Noise `CipherState`, section 5.1.
+///
+/// `n` starts at zero and is incremented for every message.  The value 2^64-1 is
+/// reserved for [`NoiseCipher::rekey`] and is never handed out.
+#[derive(Clone, Debug)]
+pub struct NoiseCipherNonce {
+    cipher: NoiseCipher,
+    n: u64,
+}
+
+impl NoiseCipherNonce {
+    /// The nonce value reserved for `REKEY`.
+    pub const REKEY_NONCE: u64 = u64::MAX;
+
+    /// Start a nonce counter at zero for `cipher`.
+    pub fn new(cipher: NoiseCipher) -> Self {
+        Self { cipher, n: 0 }
+    }
+
+    /// Return the nonce for `n` and increment it.
+    ///
+    /// Returns [`Error::SequenceExhausted`] once `n` reaches [`Self::REKEY_NONCE`].
+    pub fn next_nonce(&mut self) -> Result<Nonce, Error> {
+        if self.n == Self::REKEY_NONCE {
+            return Err(Error::SequenceExhausted {
+                limit: Self::REKEY_NONCE,
+            });
+        }
+        let nonce = self.cipher.nonce(self.n);
+        self.n += 1;
+        Ok(nonce)
+    }
+
+    /// `SetNonce(n)`, for transports that carry `n` explicitly.
+    pub fn set_n(&mut self, n: u64) {
+        self.n = n;
+    }
+
+    /// Return the next value of `n`.
+    pub fn n(&self) -> u64 {
+        self.n
+    }
+
+    /// Return the cipher whose nonce encoding is used.
+    pub fn cipher(&self) -> NoiseCipher {
+        self.cipher
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use hex_literal::hex;
+
+    const KEY: [u8; 32] = hex!("1c9240a5eb55d38af333888604f6b5f0473917c1402b80099dca5cbc207075c0");
+
+    #[test]
+    fn encodings() {
+        let mut chacha = NoiseCipherNonce::new(NoiseCipher::ChaChaPoly);
+        let mut aes = NoiseCipherNonce::new(NoiseCipher::AesGcm);
+        assert_eq!(chacha.next_nonce().unwrap().to_array(), [0; 12]);
+        assert_eq!(aes.next_nonce().unwrap().to_array(), [0; 12]);
+        assert_eq!(
+            chacha.next_nonce().unwrap().to_array(),
+            hex!("000000000100000000000000")
+        );
+        assert_eq!(
+            aes.next_nonce().unwrap().to_array(),
+            hex!("000000000000000000000001")
+        );
+        assert_eq!(
+            NoiseCipher::ChaChaPoly.nonce(0x0102030405060708).to_array(),
+            hex!("000000000807060504030201")
+        );
+        assert_eq!(
+            NoiseCipher::AesGcm.nonce(0x0102030405060708).to_array(),
+            hex!("000000000102030405060708")
+        );
+    }
+
+    #[test]
+    fn reserved_nonce() {
+        let mut nonce = NoiseCipherNonce::new(NoiseCipher::AesGcm);
+        nonce.set_n(u64::MAX - 1);
+        assert_eq!(
+            nonce.next_nonce().unwrap().to_array(),
+            hex!("00000000fffffffffffffffe")
+        );
+        assert!(matches!(
+            nonce.next_nonce(),
+            Err(Error::SequenceExhausted { limit: u64::MAX })
+        ));
+        assert_eq!(nonce.n(), u64::MAX);
+    }
+
+    #[test]
+    fn rekey() {
+        // Encrypting zeros yields the keystream, so these are the raw ChaCha20 keystream
+        // from block 1 and the AES-256-CTR keystream from counter 2, both under nonce
+        // 00000000ffffffffffffffff.
+        assert_eq!(
+            NoiseCipher::ChaChaPoly.rekey(&KEY),
+            hex!("a1a9b5ea5090ad9322b63a008729e4277508af3b67a85bb63da8bb2534a44a22")
+        );
+        assert_eq!(
+            NoiseCipher::AesGcm.rekey(&KEY),
+            hex!("87926792e7dd2c8eaac8ebf742a01d7cdc3f4e8dda68d9ebbf950d5e795f6d94")
+        );
+        assert_ne!(
+            NoiseCipher::AesGcm.rekey(&KEY),
+            NoiseCipher::ChaChaPoly.rekey(&KEY)
+        );
+    }
+}
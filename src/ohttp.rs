@@ -0,0 +1,239 @@
+use crate::aead::{AeadKey, Reader, TAG_LEN};
+use crate::{AeadCipher, ApiMisuse, Error, Iv, NonceSequence};
+
+const FINAL_AAD: &[u8] = b"final";
+
+/// Seals a body into chunks for chunked Oblivious HTTP, draft-ietf-ohai-chunked-ohttp.
+///
+/// The nonce of chunk `counter` is the `Iv` XOR `counter`, which covers both the HPKE
+/// request context and the response `aead_nonce`.  Non-final chunks are prefixed with
+/// their length; the final chunk follows a zero length and is sealed with the "final"
+/// AAD.
+#[derive(Clone)]
+pub struct ChunkedOhttpEncoder {
+    aead: AeadKey,
+    sequence: NonceSequence,
+    finished: bool,
+}
+
+impl ChunkedOhttpEncoder {
+    /// Create an encoder from the AEAD key and base nonce.
+    pub fn new(aead: AeadCipher, key: &[u8], iv: Iv) -> Result<Self, Error> {
+        Ok(Self {
+            aead: AeadKey::new(aead, key)?,
+            sequence: NonceSequence::new(iv),
+            finished: false,
+        })
+    }
+
+    /// Seal one chunk carrying `data` and return it with its framing.
+    pub fn seal_chunk(&mut self, data: &[u8], last: bool) -> Result<Vec<u8>, Error> {
+        if self.finished {
+            return Err(ApiMisuse::RecordAfterLast.into());
+        }
+
+        let mut sealed = data.to_vec();
+        let nonce = self.sequence.next_nonce()?;
+        let aad = if last { FINAL_AAD } else { &[] };
+        let tag = self.aead.seal(&nonce, aad, &mut sealed);
+        sealed.extend_from_slice(&tag);
+
+        let mut chunk = Vec::with_capacity(sealed.len() + 8);
+        put_varint(if last { 0 } else { sealed.len() as u64 }, &mut chunk);
+        chunk.extend(sealed);
+        self.finished = last;
+        Ok(chunk)
+    }
+
+    /// Seal all of `body` into chunks carrying at most `chunk_len` bytes each.
+    ///
+    /// Returns an error if `chunk_len` is zero.
+    pub fn encode(&mut self, body: &[u8], chunk_len: usize) -> Result<Vec<u8>, Error> {
+        if chunk_len == 0 {
+            return Err(ApiMisuse::ChunkLengthZero.into());
+        }
+        let mut out = Vec::new();
+        let mut chunks = body.chunks(chunk_len).peekable();
+        if chunks.peek().is_none() {
+            return self.seal_chunk(&[], true);
+        }
+        while let Some(chunk) = chunks.next() {
+            out.extend(self.seal_chunk(chunk, chunks.peek().is_none())?);
+        }
+        Ok(out)
+    }
+}
+
+/// Opens the chunks produced by [`ChunkedOhttpEncoder`].
+///
+/// A chunk that arrives out of order fails to decrypt, and a stream that ends without
+/// the final chunk is reported as [`Error::Truncated`].
+#[derive(Clone)]
+pub struct ChunkedOhttpDecoder {
+    aead: AeadKey,
+    sequence: NonceSequence,
+    finished: bool,
+}
+
+impl ChunkedOhttpDecoder {
+    /// Create a decoder from the AEAD key and base nonce.
+    pub fn new(aead: AeadCipher, key: &[u8], iv: Iv) -> Result<Self, Error> {
+        Ok(Self {
+            aead: AeadKey::new(aead, key)?,
+            sequence: NonceSequence::new(iv),
+            finished: false,
+        })
+    }
+
+    /// Open one chunk with its framing removed.
+    pub fn open_chunk(&mut self, sealed: &[u8], last: bool) -> Result<Vec<u8>, Error> {
+        if self.finished || sealed.len() < TAG_LEN {
+            return Err(Error::InvalidRecord);
+        }
+
+        let (ciphertext, tag) = sealed.split_at(sealed.len() - TAG_LEN);
+        let mut data = ciphertext.to_vec();
+        let nonce = self.sequence.next_nonce()?;
+        let aad = if last { FINAL_AAD } else { &[] };
+        self.aead.open(&nonce, aad, &mut data, tag)?;
+        self.finished = last;
+        Ok(data)
+    }
+
+    /// Open every chunk in `stream`, which must end with the final chunk.
+    pub fn decode(&mut self, stream: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut reader = Reader(stream);
+        let mut out = Vec::with_capacity(stream.len());
+        while !reader.0.is_empty() {
+            let len = reader.varint()?;
+            if len == 0 {
+                out.extend(self.open_chunk(reader.0, true)?);
+                return Ok(out);
+            }
+            let len = usize::try_from(len).map_err(|_| Error::Truncated)?;
+            let sealed = reader.take(len)?;
+            out.extend(self.open_chunk(sealed, false)?);
+        }
+        Err(Error::Truncated)
+    }
+}
+
+/// Append a variable-length integer, RFC 9000 section 16.
+fn put_varint(v: u64, out: &mut Vec<u8>) {
+    match v {
+        0..0x40 => out.push(v as u8),
+        0x40..0x4000 => out.extend_from_slice(&(v as u16 | 0x4000).to_be_bytes()),
+        0x4000..0x4000_0000 => out.extend_from_slice(&(v as u32 | 0x8000_0000).to_be_bytes()),
+        _ => out.extend_from_slice(&(v | 0xc000_0000_0000_0000).to_be_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::Nonce;
+    use aes_gcm::aead::{Aead, Payload};
+    use aes_gcm::{Aes128Gcm, KeyInit};
+    use hex_literal::hex;
+
+    const KEY: [u8; 16] = hex!("4531685d41d65f03dc48f6b8302c05b0");
+    const IV: [u8; 12] = hex!("56d890e5accaaf011cff4b7d");
+
+    fn encoder() -> ChunkedOhttpEncoder {
+        ChunkedOhttpEncoder::new(AeadCipher::Aes128Gcm, &KEY, Iv::new(IV)).unwrap()
+    }
+
+    fn decoder() -> ChunkedOhttpDecoder {
+        ChunkedOhttpDecoder::new(AeadCipher::Aes128Gcm, &KEY, Iv::new(IV)).unwrap()
+    }
+
+    #[test]
+    fn chunk_nonces_and_framing() {
+        let stream = encoder().encode(b"abcdefg", 4).unwrap();
+        let aead = Aes128Gcm::new(&KEY.into());
+        let seal = |counter, msg: &[u8], aad: &[u8]| {
+            let nonce = Nonce::new(&Iv::new(IV), counter);
+            aead.encrypt(nonce.as_bytes().into(), Payload { msg, aad })
+                .unwrap()
+        };
+
+        let mut expected = vec![20];
+        expected.extend(seal(0, b"abcd", b""));
+        expected.push(0);
+        expected.extend(seal(1, b"efg", b"final"));
+        assert_eq!(stream, expected);
+        assert_eq!(decoder().decode(&stream).unwrap(), b"abcdefg");
+    }
+
+    #[test]
+    fn round_trip() {
+        let body = (0..=255).collect::<Vec<u8>>();
+        for chunk_len in [1, 16, 100, 255, 256, 1000] {
+            let stream = encoder().encode(&body, chunk_len).unwrap();
+            assert_eq!(decoder().decode(&stream).unwrap(), body);
+        }
+        assert!(matches!(
+            encoder().encode(b"abc", 0),
+            Err(Error::Api(ApiMisuse::ChunkLengthZero))
+        ));
+        let stream = encoder().encode(&[], 16).unwrap();
+        assert_eq!(stream.len(), 1 + TAG_LEN);
+        assert_eq!(decoder().decode(&stream).unwrap(), b"");
+    }
+
+    #[test]
+    fn truncated_and_reordered() {
+        let mut encoder = encoder();
+        let first = encoder.seal_chunk(b"first", false).unwrap();
+        let second = encoder.seal_chunk(b"second", false).unwrap();
+        let last = encoder.seal_chunk(b"last", true).unwrap();
+        assert!(matches!(
+            encoder.seal_chunk(b"more", true),
+            Err(Error::Api(ApiMisuse::RecordAfterLast))
+        ));
+
+        let stream = [first.as_slice(), &second].concat();
+        assert!(matches!(decoder().decode(&stream), Err(Error::Truncated)));
+        assert!(matches!(
+            decoder().decode(&stream[..10]),
+            Err(Error::Truncated)
+        ));
+
+        let stream = [second.as_slice(), &first, &last].concat();
+        assert!(matches!(
+            decoder().decode(&stream),
+            Err(Error::DecryptError)
+        ));
+
+        // a non-final chunk cannot be passed off as the final one
+        let mut stream = first.clone();
+        stream.push(0);
+        stream.extend_from_slice(&second[1..]);
+        assert!(matches!(
+            decoder().decode(&stream),
+            Err(Error::DecryptError)
+        ));
+
+        let stream = [first.as_slice(), &second, &last].concat();
+        assert_eq!(decoder().decode(&stream).unwrap(), b"firstsecondlast");
+    }
+
+    #[test]
+    fn varint() {
+        for v in [
+            0,
+            63,
+            64,
+            16383,
+            16384,
+            (1 << 30) - 1,
+            1 << 30,
+            (1 << 62) - 1,
+        ] {
+            let mut buf = Vec::new();
+            put_varint(v, &mut buf);
+            assert_eq!(Reader(&buf).varint().unwrap(), v);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use crate::reconstruct::closest;
+use crate::{ApiMisuse, Error, NONCE_LEN, NonceSequence};
+
+/// QUIC packet numbers are in the range 0 to 2^62-1.
+pub const QUIC_PN_LIMIT: u64 = NonceSequence::<NONCE_LEN>::QUIC_LIMIT;
+
+/// A packet number truncated to the 1 to 4 bytes sent on the wire.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TruncatedPacketNumber {
+    value: u32,
+    len: usize,
+}
+
+impl TruncatedPacketNumber {
+    /// Read a big-endian truncated packet number of 1 to 4 bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.is_empty() || bytes.len() > 4 {
+            return Err(ApiMisuse::PacketNumberLength { len: bytes.len() }.into());
+        }
+        let value = bytes
+            .iter()
+            .fold(0u32, |value, b| (value << 8) | u32::from(*b));
+        Ok(Self {
+            value,
+            len: bytes.len(),
+        })
+    }
+
+    /// Write the truncated packet number in big-endian to the start of `out`.
+    pub fn write(&self, out: &mut [u8]) -> Result<usize, Error> {
+        let Some(out) = out.get_mut(..self.len) else {
+            return Err(Error::MessageTooShort {
+                expected: self.len,
+                actual: out.len(),
+            });
+        };
+        out.copy_from_slice(&self.value.to_be_bytes()[4 - self.len..]);
+        Ok(self.len)
+    }
+
+    /// Return the truncated value.
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+
+    /// Return the encoded length in bytes.
+    #[expect(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Return the encoded length in bits.
+    pub fn bits(&self) -> u32 {
+        8 * self.len as u32
+    }
+
+    /// Rebuild the full packet number, see [`decode_packet_number`].
+    pub fn decode(&self, largest_pn: Option<u64>) -> Result<u64, Error> {
+        decode_packet_number(largest_pn, self.value, self.bits())
+    }
+}
+
+/// Truncate `full_pn` to the fewest bytes a peer that acknowledged `largest_acked` can
+/// decode, RFC 9000 appendix A.2.
+///
+/// Returns an error if `full_pn` is not below [`QUIC_PN_LIMIT`] or more than 2^31
+/// packets are unacknowledged.
+pub fn encode_packet_number(
+    full_pn: u64,
+    largest_acked: Option<u64>,
+) -> Result<TruncatedPacketNumber, Error> {
+    if full_pn >= QUIC_PN_LIMIT {
+        return Err(ApiMisuse::PacketNumberExceedsLimit { pn: full_pn }.into());
+    }
+    let num_unacked = match largest_acked {
+        Some(largest_acked) => full_pn.saturating_sub(largest_acked),
+        None => full_pn
+            .checked_add(1)
+            .ok_or(ApiMisuse::PacketNumberExceedsLimit { pn: full_pn })?,
+    };
+
+    // at least one more bit than log2(num_unacked)
+    let Some(len) = (1..=4).find(|len| num_unacked <= 1 << (8 * len - 1)) else {
+        return Err(ApiMisuse::PacketNumberUnencodable {
+            full_pn,
+            largest_acked,
+        }
+        .into());
+    };
+
+    Ok(TruncatedPacketNumber {
+        value: (full_pn & ((1 << (8 * len)) - 1)) as u32,
+        len,
+    })
+}
+
+/// Rebuild the full packet number from the `pn_nbits` bits sent on the wire,
+/// RFC 9000 appendix A.3.
+///
+/// `largest_pn` is the largest packet number successfully processed in the packet
+/// number space, or `None` if there is none yet.
+///
+/// Returns an error if `pn_nbits` is not 8, 16, 24 or 32, or if `largest_pn` is not
+/// below [`QUIC_PN_LIMIT`].
+pub fn decode_packet_number(
+    largest_pn: Option<u64>,
+    truncated_pn: u32,
+    pn_nbits: u32,
+) -> Result<u64, Error> {
+    if !matches!(pn_nbits, 8 | 16 | 24 | 32) {
+        return Err(ApiMisuse::TruncatedWidth { bits: pn_nbits }.into());
+    }
+    let expected_pn = match largest_pn {
+        Some(pn) if pn >= QUIC_PN_LIMIT => {
+            return Err(ApiMisuse::PacketNumberExceedsLimit { pn }.into());
+        }
+        Some(pn) => pn + 1,
+        None => 0,
+    };
+    let truncated_pn = u64::from(truncated_pn) & ((1 << pn_nbits) - 1);
+    Ok(closest(expected_pn, truncated_pn, pn_nbits, QUIC_PN_LIMIT))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use hex_literal::hex;
+
+    // RFC 9000 appendix A.2
+    #[test]
+    fn encode_examples() {
+        let pn = encode_packet_number(0xac5c02, Some(0xabe8b3)).unwrap();
+        assert_eq!((pn.value(), pn.len()), (0x5c02, 2));
+
+        let pn = encode_packet_number(0xace8fe, Some(0xabe8b3)).unwrap();
+        assert_eq!((pn.value(), pn.len()), (0xace8fe, 3));
+    }
+
+    // RFC 9000 appendix A.3
+    #[test]
+    fn decode_example() {
+        assert_eq!(
+            decode_packet_number(Some(0xa82f30ea), 0x9b32, 16).unwrap(),
+            0xa82f9b32
+        );
+    }
+
+    #[test]
+    fn invalid_inputs() {
+        for bits in [0, 12, 40, 64] {
+            assert!(matches!(
+                decode_packet_number(None, 0, bits),
+                Err(Error::Api(ApiMisuse::TruncatedWidth { .. }))
+            ));
+        }
+        for largest_pn in [QUIC_PN_LIMIT, u64::MAX] {
+            assert!(matches!(
+                decode_packet_number(Some(largest_pn), 0, 8),
+                Err(Error::Api(ApiMisuse::PacketNumberExceedsLimit { .. }))
+            ));
+        }
+        for full_pn in [QUIC_PN_LIMIT, u64::MAX] {
+            assert!(matches!(
+                encode_packet_number(full_pn, None),
+                Err(Error::Api(ApiMisuse::PacketNumberExceedsLimit { .. }))
+            ));
+        }
+        assert_eq!(
+            decode_packet_number(Some(QUIC_PN_LIMIT - 1), 0xff, 8).unwrap(),
+            QUIC_PN_LIMIT - 1
+        );
+    }
+
+    #[test]
+    fn encode_edges() {
+        let pn = encode_packet_number(0, None).unwrap();
+        assert_eq!((pn.value(), pn.len()), (0, 1));
+        let pn = encode_packet_number(127, None).unwrap();
+        assert_eq!(pn.len(), 1);
+        let pn = encode_packet_number(128, None).unwrap();
+        assert_eq!(pn.len(), 2);
+        let pn = encode_packet_number((1 << 31) + 10, Some(11)).unwrap();
+        assert_eq!(pn.len(), 4);
+        assert!(encode_packet_number((1 << 31) + 10, Some(9)).is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        for (full_pn, largest_acked) in [
+            (0, None),
+            (0x1234, Some(0x1200)),
+            (0xac5c02, Some(0xabe8b3)),
+            (QUIC_PN_LIMIT - 1, Some(QUIC_PN_LIMIT - 100)),
+            (0x7fff_ffff, None),
+        ] {
+            let pn = encode_packet_number(full_pn, largest_acked).unwrap();
+            let mut wire = [0u8; 4];
+            let len = pn.write(&mut wire).unwrap();
+            let read = TruncatedPacketNumber::from_bytes(&wire[..len]).unwrap();
+            assert_eq!(read, pn);
+            assert_eq!(read.decode(largest_acked).unwrap(), full_pn);
+        }
+    }
+
+    #[test]
+    fn wire_bytes() {
+        let pn = TruncatedPacketNumber::from_bytes(&hex!("9b32")).unwrap();
+        assert_eq!(pn.bits(), 16);
+        assert_eq!(pn.decode(Some(0xa82f30ea)).unwrap(), 0xa82f9b32);
+
+        assert!(TruncatedPacketNumber::from_bytes(&[]).is_err());
+        assert!(TruncatedPacketNumber::from_bytes(&[0; 5]).is_err());
+        assert!(pn.write(&mut [0; 1]).is_err());
+    }
+}
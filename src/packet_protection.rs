@@ -0,0 +1,504 @@
+use subtle::ConstantTimeEq;
+
+use crate::aead::{AeadCipher, AeadKey, Reader, TAG_LEN};
+use crate::quic::{QuicKeys, QuicVersion, Side};
+use crate::{
+    ApiMisuse, Error, HeaderProtectionKey, Iv, Nonce, SAMPLE_LEN, TruncatedPacketNumber, xor,
+};
+
+/// A packet received and opened by [`PacketProtection::open`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpenedPacket {
+    /// The full packet number.
+    pub pn: u64,
+    /// The header with protection removed.
+    pub header: Vec<u8>,
+    /// The decrypted payload.
+    pub payload: Vec<u8>,
+    /// The key phase bit of a short header packet.
+    pub key_phase: Option<bool>,
+    /// The number of bytes of the input that belong to this packet, which is less than
+    /// the input length when long header packets are coalesced.
+    pub len: usize,
+}
+
+/// QUIC packet protection, RFC 9001 section 5.
+///
+/// Combines the AEAD key and `Iv` that protect the payload with the key that protects
+/// the header.
+#[derive(Clone)]
+pub struct PacketProtection {
+    aead: AeadKey,
+    iv: Iv,
+    hp: HeaderProtectionKey,
+}
+
+impl PacketProtection {
+    /// Create packet protection from the packet key, `iv` and header protection key.
+    pub fn new(aead: AeadCipher, key: &[u8], iv: Iv, hp: &[u8]) -> Result<Self, Error> {
+        let hp = match aead {
+            AeadCipher::Aes128Gcm | AeadCipher::Aes256Gcm => HeaderProtectionKey::aes(hp)?,
+            AeadCipher::ChaCha20Poly1305 => {
+                HeaderProtectionKey::chacha20(hp.try_into().map_err(|_| {
+                    ApiMisuse::KeyLengthMismatch {
+                        expected: 32,
+                        actual: hp.len(),
+                    }
+                })?)
+            }
+        };
+        Ok(Self {
+            aead: AeadKey::new(aead, key)?,
+            iv,
+            hp,
+        })
+    }
+
+    /// Create packet protection from keys derived with [`QuicKeys::from_secret`].
+    pub fn from_keys<const K: usize>(aead: AeadCipher, keys: &QuicKeys<K>) -> Result<Self, Error> {
+        Self::new(aead, &keys.key, keys.iv.clone(), &keys.hp)
+    }
+
+    /// Create the AEAD_AES_128_GCM Initial packet protection for `side` from the
+    /// client's Destination Connection ID.
+    pub fn initial(version: QuicVersion, side: Side, dcid: &[u8]) -> Self {
+        Self::from_keys(
+            AeadCipher::Aes128Gcm,
+            &QuicKeys::initial(version, side, dcid),
+        )
+        .expect("initial keys have AES-128 lengths")
+    }
+
+    /// Protect a packet with packet number `pn`.
+    ///
+    /// `header` is the unprotected header, ending with the truncated packet number whose
+    /// length is given by the low two bits of the first byte.  For long headers the
+    /// Length field must already account for the payload and the AEAD tag.
+    pub fn seal(&self, header: &[u8], pn: u64, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        let Some(first) = header.first() else {
+            return Err(Error::InvalidPacket);
+        };
+        let pn_len = usize::from(first & 0x03) + 1;
+        if header.len() < 1 + pn_len {
+            return Err(Error::InvalidPacket);
+        }
+        let pn_offset = header.len() - pn_len;
+
+        let mut packet = Vec::with_capacity(header.len() + payload.len() + TAG_LEN);
+        packet.extend_from_slice(header);
+        packet.extend_from_slice(payload);
+        let nonce = Nonce::quic(None, &self.iv, pn);
+        let tag = self.aead.seal(&nonce, header, &mut packet[header.len()..]);
+        packet.extend_from_slice(&tag);
+
+        let sample_offset = pn_offset + 4;
+        if packet.len() < sample_offset + SAMPLE_LEN {
+            return Err(Error::MessageTooShort {
+                expected: sample_offset + SAMPLE_LEN,
+                actual: packet.len(),
+            });
+        }
+        let mask = self.hp.mask_from(&packet[sample_offset..])?;
+        packet[0] ^= mask[0] & first_byte_mask(packet[0]);
+        xor(&mut packet[pn_offset..pn_offset + pn_len], &mask[1..]);
+        Ok(packet)
+    }
+
+    /// Remove protection from the first packet in `packet`.
+    ///
+    /// `short_dcid_len` is the length of the connection IDs this endpoint issued, which
+    /// short headers do not encode.  `largest_pn` is the largest packet number
+    /// successfully processed in the packet number space.
+    pub fn open(
+        &self,
+        packet: &[u8],
+        short_dcid_len: usize,
+        largest_pn: Option<u64>,
+    ) -> Result<OpenedPacket, Error> {
+        let (pn_offset, len) = packet_number_offset(packet, short_dcid_len)?;
+        let sample_offset = pn_offset + 4;
+        if len < sample_offset + SAMPLE_LEN {
+            return Err(Error::MessageTooShort {
+                expected: sample_offset + SAMPLE_LEN,
+                actual: len,
+            });
+        }
+
+        let mut packet = packet[..len].to_vec();
+        let mask = self.hp.mask_from(&packet[sample_offset..])?;
+        packet[0] ^= mask[0] & first_byte_mask(packet[0]);
+        let pn_len = usize::from(packet[0] & 0x03) + 1;
+        xor(&mut packet[pn_offset..pn_offset + pn_len], &mask[1..]);
+
+        let truncated = TruncatedPacketNumber::from_bytes(&packet[pn_offset..pn_offset + pn_len])?;
+        let pn = truncated.decode(largest_pn)?;
+
+        let header_len = pn_offset + pn_len;
+        if len < header_len + TAG_LEN {
+            return Err(Error::InvalidPacket);
+        }
+        let (header, body) = packet.split_at_mut(header_len);
+        let (payload, tag) = body.split_at_mut(body.len() - TAG_LEN);
+        let nonce = Nonce::quic(None, &self.iv, pn);
+        self.aead.open(&nonce, header, payload, tag)?;
+
+        let long = header[0] & 0x80 != 0;
+        let key_phase = (!long).then_some(header[0] & 0x04 != 0);
+        let payload = payload.to_vec();
+        packet.truncate(header_len);
+        Ok(OpenedPacket {
+            pn,
+            header: packet,
+            payload,
+            key_phase,
+            len,
+        })
+    }
+}
+
+/// The bits of the first byte covered by header protection: 4 for long headers and 5
+/// for short headers.
+fn first_byte_mask(first: u8) -> u8 {
+    if first & 0x80 != 0 { 0x0f } else { 0x1f }
+}
+
+/// Find the packet number offset and the packet length.
+///
+/// For long headers the length comes from the Length field, for short headers it is the
+/// rest of the input.  A header cut short is an [`Error::InvalidPacket`].
+fn packet_number_offset(packet: &[u8], short_dcid_len: usize) -> Result<(usize, usize), Error> {
+    parse_header(packet, short_dcid_len).map_err(|e| match e {
+        Error::Truncated => Error::InvalidPacket,
+        e => e,
+    })
+}
+
+fn parse_header(packet: &[u8], short_dcid_len: usize) -> Result<(usize, usize), Error> {
+    let mut reader = Reader(packet);
+    let first = reader.u8()?;
+    if first & 0x80 == 0 {
+        let pn_offset = 1 + short_dcid_len;
+        if packet.len() < pn_offset {
+            return Err(Error::InvalidPacket);
+        }
+        return Ok((pn_offset, packet.len()));
+    }
+
+    let version = u32::from_be_bytes(reader.take(4)?.try_into().unwrap());
+    let version = QuicVersion::from_wire(version).ok_or(Error::InvalidPacket)?;
+    let dcid_len = usize::from(reader.u8()?);
+    reader.take(dcid_len)?;
+    let scid_len = usize::from(reader.u8()?);
+    reader.take(scid_len)?;
+
+    let initial = match version {
+        QuicVersion::V1 | QuicVersion::Draft29 => 0b00,
+        QuicVersion::V2 => 0b01,
+    };
+    let retry = match version {
+        QuicVersion::V1 | QuicVersion::Draft29 => 0b11,
+        QuicVersion::V2 => 0b00,
+    };
+    let packet_type = (first >> 4) & 0x03;
+    if packet_type == retry {
+        return Err(Error::InvalidPacket);
+    }
+    if packet_type == initial {
+        let token_len = usize::try_from(reader.varint()?).map_err(|_| Error::InvalidPacket)?;
+        reader.take(token_len)?;
+    }
+    let length = usize::try_from(reader.varint()?).map_err(|_| Error::InvalidPacket)?;
+
+    let pn_offset = packet.len() - reader.0.len();
+    let len = pn_offset
+        .checked_add(length)
+        .filter(|len| *len <= packet.len())
+        .ok_or(Error::InvalidPacket)?;
+    Ok((pn_offset, len))
+}
+
+/// Compute the Retry Integrity Tag, RFC 9001 section 5.8.
+///
+/// `retry` is the Retry packet without the tag, and `odcid` the Destination Connection
+/// ID of the client's first Initial packet.
+///
+/// Returns [`ApiMisuse::ConnectionIdLength`] if `odcid` is longer than 255 bytes.
+pub fn retry_integrity_tag(
+    version: QuicVersion,
+    odcid: &[u8],
+    retry: &[u8],
+) -> Result<[u8; TAG_LEN], Error> {
+    let (key, nonce): ([u8; 16], [u8; 12]) = match version {
+        QuicVersion::V1 => (
+            [
+                0xbe, 0x0c, 0x69, 0x0b, 0x9f, 0x66, 0x57, 0x5a, 0x1d, 0x76, 0x6b, 0x54, 0xe3, 0x68,
+                0xc8, 0x4e,
+            ],
+            [
+                0x46, 0x15, 0x99, 0xd3, 0x5d, 0x63, 0x2b, 0xf2, 0x23, 0x98, 0x25, 0xbb,
+            ],
+        ),
+        QuicVersion::V2 => (
+            [
+                0x8f, 0xb4, 0xb0, 0x1b, 0x56, 0xac, 0x48, 0xe2, 0x60, 0xfb, 0xcb, 0xce, 0xad, 0x7c,
+                0xcc, 0x92,
+            ],
+            [
+                0xd8, 0x69, 0x69, 0xbc, 0x2d, 0x7c, 0x6d, 0x99, 0x90, 0xef, 0xb0, 0x4a,
+            ],
+        ),
+        QuicVersion::Draft29 => (
+            [
+                0xcc, 0xce, 0x18, 0x7e, 0xd0, 0x9a, 0x09, 0xd0, 0x57, 0x28, 0x15, 0x5a, 0x6c, 0xb9,
+                0x6b, 0xe1,
+            ],
+            [
+                0xe5, 0x49, 0x30, 0xf9, 0x7f, 0x21, 0x36, 0xf0, 0x53, 0x0a, 0x8c, 0x1c,
+            ],
+        ),
+    };
+
+    let odcid_len = u8::try_from(odcid.len())
+        .map_err(|_| ApiMisuse::ConnectionIdLength { len: odcid.len() })?;
+    let mut pseudo = Vec::with_capacity(1 + odcid.len() + retry.len());
+    pseudo.push(odcid_len);
+    pseudo.extend_from_slice(odcid);
+    pseudo.extend_from_slice(retry);
+
+    Ok(AeadKey::new(AeadCipher::Aes128Gcm, &key)
+        .expect("16-byte key")
+        .seal(&Nonce(nonce), &pseudo, &mut []))
+}
+
+/// Check the Retry Integrity Tag at the end of a Retry `packet`.
+///
+/// The tag is compared in constant time.  A packet shorter than the tag, or an `odcid`
+/// longer than 255 bytes, fails verification.
+pub fn verify_retry(version: QuicVersion, odcid: &[u8], packet: &[u8]) -> bool {
+    let Some(split) = packet.len().checked_sub(TAG_LEN) else {
+        return false;
+    };
+    let (retry, tag) = packet.split_at(split);
+    let Ok(expected) = retry_integrity_tag(version, odcid, retry) else {
+        return false;
+    };
+    expected.ct_eq(tag).into()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use hex_literal::hex;
+
+    const DCID: [u8; 8] = hex!("8394c8f03e515708");
+
+    // The CRYPTO frame carrying the ClientHello in RFC 9001 appendix A.2
+    const CLIENT_CRYPTO: [u8; 245] = hex!(
+        "060040f1010000ed0303ebf8fa56f12939b9584a3896472ec40bb863cfd3e868"
+        "04fe3a47f06a2b69484c00000413011302010000c000000010000e00000b6578"
+        "616d706c652e636f6dff01000100000a00080006001d00170018001000070005"
+        "04616c706e000500050100000000003300260024001d00209370b2c9caa47fba"
+        "baf4559fedba753de171fa71f50f1ce15d43e994ec74d748002b000302030400"
+        "0d0010000e0403050306030203080408050806002d00020101001c0002400100"
+        "3900320408ffffffffffffffff05048000ffff07048000ffff08011001048000"
+        "75300901100f088394c8f03e51570806048000ffff"
+    );
+
+    // The protected client Initial in RFC 9001 appendix A.2
+    const CLIENT_INITIAL: [u8; 1200] = hex!(
+        "c000000001088394c8f03e5157080000449e7b9aec34d1b1c98dd7689fb8ec11"
+        "d242b123dc9bd8bab936b47d92ec356c0bab7df5976d27cd449f63300099f399"
+        "1c260ec4c60d17b31f8429157bb35a1282a643a8d2262cad67500cadb8e7378c"
+        "8eb7539ec4d4905fed1bee1fc8aafba17c750e2c7ace01e6005f80fcb7df6212"
+        "30c83711b39343fa028cea7f7fb5ff89eac2308249a02252155e2347b63d58c5"
+        "457afd84d05dfffdb20392844ae812154682e9cf012f9021a6f0be17ddd0c208"
+        "4dce25ff9b06cde535d0f920a2db1bf362c23e596d11a4f5a6cf3948838a3aec"
+        "4e15daf8500a6ef69ec4e3feb6b1d98e610ac8b7ec3faf6ad760b7bad1db4ba3"
+        "485e8a94dc250ae3fdb41ed15fb6a8e5eba0fc3dd60bc8e30c5c4287e53805db"
+        "059ae0648db2f64264ed5e39be2e20d82df566da8dd5998ccabdae053060ae6c"
+        "7b4378e846d29f37ed7b4ea9ec5d82e7961b7f25a9323851f681d582363aa5f8"
+        "9937f5a67258bf63ad6f1a0b1d96dbd4faddfcefc5266ba6611722395c906556"
+        "be52afe3f565636ad1b17d508b73d8743eeb524be22b3dcbc2c7468d54119c74"
+        "68449a13d8e3b95811a198f3491de3e7fe942b330407abf82a4ed7c1b311663a"
+        "c69890f4157015853d91e923037c227a33cdd5ec281ca3f79c44546b9d90ca00"
+        "f064c99e3dd97911d39fe9c5d0b23a229a234cb36186c4819e8b9c5927726632"
+        "291d6a418211cc2962e20fe47feb3edf330f2c603a9d48c0fcb5699dbfe58964"
+        "25c5bac4aee82e57a85aaf4e2513e4f05796b07ba2ee47d80506f8d2c25e50fd"
+        "14de71e6c418559302f939b0e1abd576f279c4b2e0feb85c1f28ff18f58891ff"
+        "ef132eef2fa09346aee33c28eb130ff28f5b766953334113211996d20011a198"
+        "e3fc433f9f2541010ae17c1bf202580f6047472fb36857fe843b19f5984009dd"
+        "c324044e847a4f4a0ab34f719595de37252d6235365e9b84392b061085349d73"
+        "203a4a13e96f5432ec0fd4a1ee65accdd5e3904df54c1da510b0ff20dcc0c77f"
+        "cb2c0e0eb605cb0504db87632cf3d8b4dae6e705769d1de354270123cb11450e"
+        "fc60ac47683d7b8d0f811365565fd98c4c8eb936bcab8d069fc33bd801b03ade"
+        "a2e1fbc5aa463d08ca19896d2bf59a071b851e6c239052172f296bfb5e724047"
+        "90a2181014f3b94a4e97d117b438130368cc39dbb2d198065ae3986547926cd2"
+        "162f40a29f0c3c8745c0f50fba3852e566d44575c29d39a03f0cda721984b6f4"
+        "40591f355e12d439ff150aab7613499dbd49adabc8676eef023b15b65bfc5ca0"
+        "6948109f23f350db82123535eb8a7433bdabcb909271a6ecbcb58b936a88cd4e"
+        "8f2e6ff5800175f113253d8fa9ca8885c2f552e657dc603f252e1a8e308f76f0"
+        "be79e2fb8f5d5fbbe2e30ecadd220723c8c0aea8078cdfcb3868263ff8f09400"
+        "54da48781893a7e49ad5aff4af300cd804a6b6279ab3ff3afb64491c85194aab"
+        "760d58a606654f9f4400e8b38591356fbf6425aca26dc85244259ff2b19c41b9"
+        "f96f3ca9ec1dde434da7d2d392b905ddf3d1f9af93d1af5950bd493f5aa731b4"
+        "056df31bd267b6b90a079831aaf579be0a39013137aac6d404f518cfd4684064"
+        "7e78bfe706ca4cf5e9c5453e9f7cfd2b8b4c8d169a44e55c88d4a9a7f9474241"
+        "e221af44860018ab0856972e194cd934"
+    );
+
+    // RFC 9001 appendix A.2
+    #[test]
+    fn client_initial() {
+        let header = hex!("c300000001088394c8f03e5157080000449e00000002");
+        let mut payload = vec![0u8; 1162];
+        payload[..CLIENT_CRYPTO.len()].copy_from_slice(&CLIENT_CRYPTO);
+
+        let client = PacketProtection::initial(QuicVersion::V1, Side::Client, &DCID);
+        let packet = client.seal(&header, 2, &payload).unwrap();
+
+        assert_eq!(packet, CLIENT_INITIAL);
+
+        let opened = client.open(&packet, 0, None).unwrap();
+        assert_eq!(opened.pn, 2);
+        assert_eq!(opened.header, header);
+        assert_eq!(opened.payload, payload);
+        assert_eq!(opened.key_phase, None);
+        assert_eq!(opened.len, 1200);
+    }
+
+    // RFC 9001 appendix A.3
+    #[test]
+    fn server_initial() {
+        let header = hex!("c1000000010008f067a5502a4262b50040750001");
+        let payload = hex!(
+            "02000000000600405a020000560303eefce7f7b37ba1d1632e96677825ddf739"
+            "88cfc79825df566dc5430b9a045a1200130100002e00330024001d00209d3c94"
+            "0d89690b84d08a60993c144eca684d1081287c834d5311bcf32bb9da1a002b00"
+            "020304"
+        );
+        let expected = hex!(
+            "cf000000010008f067a5502a4262b5004075c0d95a482cd0991cd25b0aac406a"
+            "5816b6394100f37a1c69797554780bb38cc5a99f5ede4cf73c3ec2493a1839b3"
+            "dbcba3f6ea46c5b7684df3548e7ddeb9c3bf9c73cc3f3bded74b562bfb19fb84"
+            "022f8ef4cdd93795d77d06edbb7aaf2f58891850abbdca3d20398c276456cbc4"
+            "2158407dd074ee"
+        );
+
+        let server = PacketProtection::initial(QuicVersion::V1, Side::Server, &DCID);
+        assert_eq!(server.seal(&header, 1, &payload).unwrap(), expected);
+
+        let opened = server.open(&expected, 0, None).unwrap();
+        assert_eq!(opened.pn, 1);
+        assert_eq!(opened.header, header);
+        assert_eq!(opened.payload, payload);
+    }
+
+    // RFC 9001 appendix A.4
+    #[test]
+    fn retry() {
+        let packet = hex!(
+            "ff000000010008f067a5502a4262b5746f6b656e04a265ba2eff4d829058fb3f"
+            "0f2496ba"
+        );
+        assert_eq!(
+            retry_integrity_tag(QuicVersion::V1, &DCID, &packet[..packet.len() - TAG_LEN]).unwrap(),
+            packet[packet.len() - TAG_LEN..]
+        );
+        assert!(verify_retry(QuicVersion::V1, &DCID, &packet));
+        assert!(!verify_retry(QuicVersion::V2, &DCID, &packet));
+        assert!(!verify_retry(QuicVersion::V1, &DCID[1..], &packet));
+        assert!(!verify_retry(QuicVersion::V1, &[0; 256], &packet));
+        assert!(matches!(
+            retry_integrity_tag(QuicVersion::V1, &[0; 256], &packet),
+            Err(Error::Api(ApiMisuse::ConnectionIdLength { len: 256 }))
+        ));
+
+        let mut forged = packet;
+        forged[forged.len() - 1] ^= 1;
+        assert!(!verify_retry(QuicVersion::V1, &DCID, &forged));
+        assert!(!verify_retry(
+            QuicVersion::V1,
+            &DCID,
+            &packet[..TAG_LEN - 1]
+        ));
+    }
+
+    // RFC 9369 appendix A.4 and draft-ietf-quic-tls-29 appendix A.4
+    #[test]
+    fn retry_v2_and_draft29() {
+        assert!(verify_retry(
+            QuicVersion::V2,
+            &DCID,
+            &hex!("cf6b3343cf0008f067a5502a4262b5746f6b656ec8646ce8bfe33952d955543665dcc7b6")
+        ));
+        assert!(verify_retry(
+            QuicVersion::Draft29,
+            &DCID,
+            &hex!("ffff00001d0008f067a5502a4262b5746f6b656ed16926d81f6f9ca2953a8aa4575e1e49")
+        ));
+    }
+
+    // RFC 9001 appendix A.5
+    #[test]
+    fn chacha20_short_header() {
+        let protection = PacketProtection::new(
+            AeadCipher::ChaCha20Poly1305,
+            &hex!("c6d98ff3441c3fe1b2182094f69caa2ed4b716b65488960a7a984979fb23e1c8"),
+            Iv::new(hex!("e0459b3474bdd0e44a41c144")),
+            &hex!("25a282b9e82f06f21f488917a4fc8f1b73573685608597d0efcb076b0ab7a7a4"),
+        )
+        .unwrap();
+
+        let packet = protection
+            .seal(&hex!("4200bff4"), 654360564, &hex!("01"))
+            .unwrap();
+        assert_eq!(packet, hex!("4cfe4189655e5cd55c41f69080575d7999c25a5bfb"));
+
+        let opened = protection.open(&packet, 0, Some(654360563)).unwrap();
+        assert_eq!(opened.pn, 654360564);
+        assert_eq!(opened.header, hex!("4200bff4"));
+        assert_eq!(opened.payload, hex!("01"));
+        assert_eq!(opened.key_phase, Some(false));
+    }
+
+    // RFC 9369 appendix A.5
+    #[test]
+    fn chacha20_short_header_v2() {
+        let keys = QuicKeys::<32>::from_secret(
+            QuicVersion::V2,
+            crate::HashAlgorithm::Sha256,
+            &hex!("9ac312a7f877468ebe69422748ad00a15443f18203a07d6060f688f30f21632b"),
+        )
+        .unwrap();
+        let protection = PacketProtection::from_keys(AeadCipher::ChaCha20Poly1305, &keys).unwrap();
+
+        let packet = protection
+            .seal(&hex!("4200bff4"), 654360564, &hex!("01"))
+            .unwrap();
+        assert_eq!(packet, hex!("5558b1c60ae7b6b932bc27d786f4bc2bb20f2162ba"));
+        assert_eq!(
+            protection.open(&packet, 0, Some(654360563)).unwrap().pn,
+            654360564
+        );
+    }
+
+    #[test]
+    fn tampered() {
+        let server = PacketProtection::initial(QuicVersion::V1, Side::Server, &DCID);
+        let header = hex!("c1000000010008f067a5502a4262b50040190001");
+        let mut packet = server.seal(&header, 1, &[0; 7]).unwrap();
+        assert!(server.open(&packet, 0, None).is_ok());
+
+        let last = packet.len() - 1;
+        packet[last] ^= 1;
+        assert!(matches!(
+            server.open(&packet, 0, None),
+            Err(Error::DecryptError)
+        ));
+        assert!(matches!(
+            server.open(&packet[..20], 0, None),
+            Err(Error::InvalidPacket)
+        ));
+    }
+}
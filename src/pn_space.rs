@@ -0,0 +1,248 @@
+use crate::packet_number::QUIC_PN_LIMIT;
+use crate::{Error, Iv, NONCE_LEN, Nonce};
+
+/// The QUIC encryption levels of RFC 9001, section 4.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EncryptionLevel {
+    Initial,
+    ZeroRtt,
+    Handshake,
+    OneRtt,
+}
+
+impl EncryptionLevel {
+    /// Return the packet number space used at this level.
+    ///
+    /// 0-RTT and 1-RTT packets share the application data space.
+    pub fn space(self) -> PacketNumberSpace {
+        match self {
+            Self::Initial => PacketNumberSpace::Initial,
+            Self::Handshake => PacketNumberSpace::Handshake,
+            Self::ZeroRtt | Self::OneRtt => PacketNumberSpace::ApplicationData,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// The QUIC packet number spaces of RFC 9000, section 12.3.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PacketNumberSpace {
+    Initial,
+    Handshake,
+    ApplicationData,
+}
+
+impl PacketNumberSpace {
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Clone)]
+enum Keys<const N: usize> {
+    Pending,
+    Installed(Iv<N>),
+    Discarded,
+}
+
+/// One packet number counter per space, and the `Iv` of every encryption level.
+///
+/// Nonces can only be built for a level once its `Iv` has been installed, and never
+/// again after its keys have been discarded.
+#[derive(Clone)]
+pub struct PacketNumberSpaces<const N: usize = NONCE_LEN> {
+    keys: [Keys<N>; 4],
+    next_pn: [u64; 3],
+}
+
+impl<const N: usize> PacketNumberSpaces<N> {
+    /// Create the spaces with no keys installed.
+    pub fn new() -> Self {
+        Self {
+            keys: [Keys::Pending, Keys::Pending, Keys::Pending, Keys::Pending],
+            next_pn: [0; 3],
+        }
+    }
+
+    /// Install the `Iv` for `level`.
+    ///
+    /// Installing over an existing `Iv`, as after a 1-RTT key update, keeps the packet
+    /// number counter.  Returns an error if the keys for `level` were discarded.
+    pub fn install(&mut self, level: EncryptionLevel, iv: Iv<N>) -> Result<(), Error> {
+        let keys = &mut self.keys[level.index()];
+        if let Keys::Discarded = keys {
+            return Err(Error::KeysDiscarded { level });
+        }
+        *keys = Keys::Installed(iv);
+        Ok(())
+    }
+
+    /// Discard the keys for `level`.  No further nonces are built for it.
+    pub fn discard(&mut self, level: EncryptionLevel) {
+        self.keys[level.index()] = Keys::Discarded;
+    }
+
+    /// Return whether the keys for `level` have been discarded.
+    pub fn is_discarded(&self, level: EncryptionLevel) -> bool {
+        matches!(self.keys[level.index()], Keys::Discarded)
+    }
+
+    /// Return the next packet number at `level` and its nonce, and advance the counter
+    /// of its packet number space.
+    pub fn next_nonce(&mut self, level: EncryptionLevel) -> Result<(u64, Nonce<N>), Error> {
+        let pn = self.next_pn[level.space().index()];
+        if pn >= QUIC_PN_LIMIT {
+            return Err(Error::SequenceExhausted {
+                limit: QUIC_PN_LIMIT,
+            });
+        }
+        let nonce = Nonce::new(self.iv(level)?, pn);
+        self.next_pn[level.space().index()] += 1;
+        Ok((pn, nonce))
+    }
+
+    /// Return the nonce for a packet received at `level` with packet number `pn`.
+    pub fn nonce_for(&self, level: EncryptionLevel, pn: u64) -> Result<Nonce<N>, Error> {
+        Ok(Nonce::new(self.iv(level)?, pn))
+    }
+
+    /// Return the next packet number in `space`.
+    pub fn next_pn(&self, space: PacketNumberSpace) -> u64 {
+        self.next_pn[space.index()]
+    }
+
+    fn iv(&self, level: EncryptionLevel) -> Result<&Iv<N>, Error> {
+        match &self.keys[level.index()] {
+            Keys::Installed(iv) => Ok(iv),
+            Keys::Pending => Err(Error::KeysUnavailable { level }),
+            Keys::Discarded => Err(Error::KeysDiscarded { level }),
+        }
+    }
+}
+
+impl<const N: usize> Default for PacketNumberSpaces<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use hex_literal::hex;
+
+    const INITIAL: [u8; 12] = hex!("fa044b2f42a3fd3b46fb255c");
+    const ZERO_RTT: [u8; 12] = hex!("000102030405060708090a0b");
+    const ONE_RTT: [u8; 12] = hex!("e0459b3474bdd0e44a41c144");
+
+    #[test]
+    fn separate_spaces() {
+        let mut spaces = PacketNumberSpaces::new();
+        spaces
+            .install(EncryptionLevel::Initial, Iv::new(INITIAL))
+            .unwrap();
+        spaces
+            .install(EncryptionLevel::OneRtt, Iv::new(ONE_RTT))
+            .unwrap();
+
+        assert_eq!(
+            spaces.next_nonce(EncryptionLevel::Initial).unwrap(),
+            (0, Nonce::new(&Iv::new(INITIAL), 0))
+        );
+        assert_eq!(spaces.next_nonce(EncryptionLevel::Initial).unwrap().0, 1);
+        assert_eq!(
+            spaces.next_nonce(EncryptionLevel::OneRtt).unwrap(),
+            (0, Nonce::new(&Iv::new(ONE_RTT), 0))
+        );
+        assert_eq!(spaces.next_pn(PacketNumberSpace::Initial), 2);
+        assert_eq!(spaces.next_pn(PacketNumberSpace::Handshake), 0);
+        assert!(matches!(
+            spaces.next_nonce(EncryptionLevel::Handshake),
+            Err(Error::KeysUnavailable {
+                level: EncryptionLevel::Handshake
+            })
+        ));
+    }
+
+    #[test]
+    fn application_space_is_shared() {
+        let mut spaces = PacketNumberSpaces::new();
+        spaces
+            .install(EncryptionLevel::ZeroRtt, Iv::new(ZERO_RTT))
+            .unwrap();
+        assert_eq!(spaces.next_nonce(EncryptionLevel::ZeroRtt).unwrap().0, 0);
+        assert_eq!(spaces.next_nonce(EncryptionLevel::ZeroRtt).unwrap().0, 1);
+
+        spaces
+            .install(EncryptionLevel::OneRtt, Iv::new(ONE_RTT))
+            .unwrap();
+        spaces.discard(EncryptionLevel::ZeroRtt);
+        assert_eq!(
+            spaces.next_nonce(EncryptionLevel::OneRtt).unwrap(),
+            (2, Nonce::new(&Iv::new(ONE_RTT), 2))
+        );
+        assert_eq!(spaces.next_pn(PacketNumberSpace::ApplicationData), 3);
+    }
+
+    #[test]
+    fn discarded_keys() {
+        let mut spaces = PacketNumberSpaces::new();
+        spaces
+            .install(EncryptionLevel::Initial, Iv::new(INITIAL))
+            .unwrap();
+        spaces.next_nonce(EncryptionLevel::Initial).unwrap();
+        spaces.discard(EncryptionLevel::Initial);
+
+        assert!(spaces.is_discarded(EncryptionLevel::Initial));
+        assert!(matches!(
+            spaces.next_nonce(EncryptionLevel::Initial),
+            Err(Error::KeysDiscarded {
+                level: EncryptionLevel::Initial
+            })
+        ));
+        assert!(spaces.nonce_for(EncryptionLevel::Initial, 0).is_err());
+        assert!(
+            spaces
+                .install(EncryptionLevel::Initial, Iv::new(INITIAL))
+                .is_err()
+        );
+        assert_eq!(spaces.next_pn(PacketNumberSpace::Initial), 1);
+    }
+
+    #[test]
+    fn key_update_keeps_counter() {
+        let mut spaces = PacketNumberSpaces::new();
+        spaces
+            .install(EncryptionLevel::OneRtt, Iv::new(ONE_RTT))
+            .unwrap();
+        spaces.next_nonce(EncryptionLevel::OneRtt).unwrap();
+        spaces
+            .install(EncryptionLevel::OneRtt, Iv::new(ZERO_RTT))
+            .unwrap();
+        assert_eq!(
+            spaces.next_nonce(EncryptionLevel::OneRtt).unwrap(),
+            (1, Nonce::new(&Iv::new(ZERO_RTT), 1))
+        );
+    }
+
+    #[test]
+    fn exhausted() {
+        let mut spaces = PacketNumberSpaces::new();
+        spaces
+            .install(EncryptionLevel::Handshake, Iv::new(INITIAL))
+            .unwrap();
+        spaces.next_pn[PacketNumberSpace::Handshake.index()] = QUIC_PN_LIMIT - 1;
+        assert_eq!(
+            spaces.next_nonce(EncryptionLevel::Handshake).unwrap().0,
+            QUIC_PN_LIMIT - 1
+        );
+        assert!(matches!(
+            spaces.next_nonce(EncryptionLevel::Handshake),
+            Err(Error::SequenceExhausted { .. })
+        ));
+    }
+}
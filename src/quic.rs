@@ -0,0 +1,274 @@
+use hkdf::Hkdf;
+use sha2::Sha256;
+
+use crate::tls13::hkdf_expand_label;
+use crate::{Error, HashAlgorithm, Iv, Nonce};
+
+/// A QUIC version with its own Initial salt and key derivation labels.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuicVersion {
+    /// QUIC version 1, RFC 9000 and RFC 9001.
+    V1,
+    /// QUIC version 2, RFC 9369.
+    V2,
+    /// draft-ietf-quic-tls-29.
+    Draft29,
+}
+
+impl QuicVersion {
+    /// Return the version for the 32-bit version field of a long header.
+    pub const fn from_wire(version: u32) -> Option<Self> {
+        match version {
+            0x0000_0001 => Some(Self::V1),
+            0x6b33_43cf => Some(Self::V2),
+            0xff00_001d => Some(Self::Draft29),
+            _ => None,
+        }
+    }
+
+    /// Return the 32-bit version field of a long header.
+    pub const fn to_wire(self) -> u32 {
+        match self {
+            Self::V1 => 0x0000_0001,
+            Self::V2 => 0x6b33_43cf,
+            Self::Draft29 => 0xff00_001d,
+        }
+    }
+
+    /// Return the salt used to derive Initial secrets.
+    pub const fn initial_salt(self) -> &'static [u8; 20] {
+        match self {
+            Self::V1 => &[
+                0x38, 0x76, 0x2c, 0xf7, 0xf5, 0x59, 0x34, 0xb3, 0x4d, 0x17, 0x9a, 0xe6, 0xa4, 0xc8,
+                0x0c, 0xad, 0xcc, 0xbb, 0x7f, 0x0a,
+            ],
+            Self::V2 => &[
+                0x0d, 0xed, 0xe3, 0xde, 0xf7, 0x00, 0xa6, 0xdb, 0x81, 0x93, 0x81, 0xbe, 0x6e, 0x26,
+                0x9d, 0xcb, 0xf9, 0xbd, 0x2e, 0xd9,
+            ],
+            Self::Draft29 => &[
+                0xaf, 0xbf, 0xec, 0x28, 0x99, 0x93, 0xd2, 0x4c, 0x9e, 0x97, 0x86, 0xf1, 0x9c, 0x61,
+                0x11, 0xe0, 0x43, 0x90, 0xa8, 0x99,
+            ],
+        }
+    }
+
+    /// Return the labels for the packet protection key, IV, header protection key and
+    /// key update.
+    pub(crate) const fn labels(self) -> QuicLabels {
+        match self {
+            Self::V1 | Self::Draft29 => QuicLabels {
+                key: b"quic key",
+                iv: b"quic iv",
+                hp: b"quic hp",
+                ku: b"quic ku",
+            },
+            Self::V2 => QuicLabels {
+                key: b"quicv2 key",
+                iv: b"quicv2 iv",
+                hp: b"quicv2 hp",
+                ku: b"quicv2 ku",
+            },
+        }
+    }
+}
+
+pub(crate) struct QuicLabels {
+    pub(crate) key: &'static [u8],
+    pub(crate) iv: &'static [u8],
+    pub(crate) hp: &'static [u8],
+    pub(crate) ku: &'static [u8],
+}
+
+/// The endpoint whose packets a secret protects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Client,
+    Server,
+}
+
+/// Derive the client or server Initial secret from the client's Destination Connection ID.
+///
+/// This follows RFC 9001 section 5.2 with the salt of `version`.
+pub fn quic_initial_secret(version: QuicVersion, side: Side, dcid: &[u8]) -> [u8; 32] {
+    let (initial_secret, _) = Hkdf::<Sha256>::extract(Some(version.initial_salt()), dcid);
+    let label: &[u8] = match side {
+        Side::Client => b"client in",
+        Side::Server => b"server in",
+    };
+    let mut secret = [0u8; 32];
+    hkdf_expand_label(
+        HashAlgorithm::Sha256,
+        &initial_secret,
+        b"tls13 ",
+        label,
+        &[],
+        &mut secret,
+    )
+    .expect("initial secret is a SHA-256 output");
+    secret
+}
+
+impl<const N: usize> Iv<N> {
+    /// Derive the packet protection IV from a QUIC secret.
+    ///
+    /// This is `HKDF-Expand-Label(secret, "quic iv", "", N)`, or "quicv2 iv" for QUIC v2.
+    pub fn from_quic_secret(
+        version: QuicVersion,
+        hash: HashAlgorithm,
+        secret: &[u8],
+    ) -> Result<Self, Error> {
+        let mut iv = [0u8; N];
+        hkdf_expand_label(hash, secret, b"tls13 ", version.labels().iv, &[], &mut iv)?;
+        Ok(Self::new(iv))
+    }
+}
+
+/// The packet protection key, IV and header protection key derived from a QUIC secret.
+///
+/// `K` is the AEAD key length, which is also the header protection key length.
+#[derive(Clone)]
+pub struct QuicKeys<const K: usize = 16> {
+    pub key: [u8; K],
+    pub iv: Iv,
+    pub hp: [u8; K],
+}
+
+impl<const K: usize> QuicKeys<K> {
+    /// Derive the keys from a QUIC secret, as in RFC 9001 section 5.1.
+    pub fn from_secret(
+        version: QuicVersion,
+        hash: HashAlgorithm,
+        secret: &[u8],
+    ) -> Result<Self, Error> {
+        let labels = version.labels();
+        let mut key = [0u8; K];
+        hkdf_expand_label(hash, secret, b"tls13 ", labels.key, &[], &mut key)?;
+        let mut hp = [0u8; K];
+        hkdf_expand_label(hash, secret, b"tls13 ", labels.hp, &[], &mut hp)?;
+        Ok(Self {
+            key,
+            iv: Iv::from_quic_secret(version, hash, secret)?,
+            hp,
+        })
+    }
+
+    /// Return the nonce for packet number `pn`.
+    pub fn nonce(&self, pn: u64) -> Nonce {
+        Nonce::quic(None, &self.iv, pn)
+    }
+}
+
+impl QuicKeys {
+    /// Derive the AEAD_AES_128_GCM Initial keys for `side` from the client's Destination
+    /// Connection ID.
+    pub fn initial(version: QuicVersion, side: Side, dcid: &[u8]) -> Self {
+        let secret = quic_initial_secret(version, side, dcid);
+        Self::from_secret(version, HashAlgorithm::Sha256, &secret)
+            .expect("initial secret is a SHA-256 output")
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use hex_literal::hex;
+
+    const DCID: [u8; 8] = hex!("8394c8f03e515708");
+
+    fn check(version: QuicVersion, side: Side, key: [u8; 16], iv: [u8; 12], hp: [u8; 16]) {
+        let keys = QuicKeys::initial(version, side, &DCID);
+        assert_eq!(keys.key, key);
+        assert_eq!(keys.iv.as_ref(), &iv);
+        assert_eq!(keys.hp, hp);
+    }
+
+    // RFC 9001 appendix A.1
+    #[test]
+    fn v1_initial() {
+        assert_eq!(
+            quic_initial_secret(QuicVersion::V1, Side::Client, &DCID),
+            hex!("c00cf151ca5be075ed0ebfb5c80323c42d6b7db67881289af4008f1f6c357aea")
+        );
+        assert_eq!(
+            quic_initial_secret(QuicVersion::V1, Side::Server, &DCID),
+            hex!("3c199828fd139efd216c155ad844cc81fb82fa8d7446fa7d78be803acdda951b")
+        );
+        check(
+            QuicVersion::V1,
+            Side::Client,
+            hex!("1f369613dd76d5467730efcbe3b1a22d"),
+            hex!("fa044b2f42a3fd3b46fb255c"),
+            hex!("9f50449e04a0e810283a1e9933adedd2"),
+        );
+        check(
+            QuicVersion::V1,
+            Side::Server,
+            hex!("cf3a5331653c364c88f0f379b6067e37"),
+            hex!("0ac1493ca1905853b0bba03e"),
+            hex!("c206b8d9b9f0f37644430b490eeaa314"),
+        );
+    }
+
+    // RFC 9369 appendix A.1
+    #[test]
+    fn v2_initial() {
+        assert_eq!(
+            quic_initial_secret(QuicVersion::V2, Side::Client, &DCID),
+            hex!("14ec9d6eb9fd7af83bf5a668bc17a7e283766aade7ecd0891f70f9ff7f4bf47b")
+        );
+        assert_eq!(
+            quic_initial_secret(QuicVersion::V2, Side::Server, &DCID),
+            hex!("0263db1782731bf4588e7e4d93b7463907cb8cd8200b5da55a8bd488eafc37c1")
+        );
+        check(
+            QuicVersion::V2,
+            Side::Client,
+            hex!("8b1a0bc121284290a29e0971b5cd045d"),
+            hex!("91f73e2351d8fa91660e909f"),
+            hex!("45b95e15235d6f45a6b19cbcb0294ba9"),
+        );
+        check(
+            QuicVersion::V2,
+            Side::Server,
+            hex!("82db637861d55e1d011f19ea71d5d2a7"),
+            hex!("dd13c276499c0249d3310652"),
+            hex!("edf6d05c83121201b436e16877593c3a"),
+        );
+    }
+
+    // draft-ietf-quic-tls-29 appendix A.1
+    #[test]
+    fn draft29_initial() {
+        check(
+            QuicVersion::Draft29,
+            Side::Client,
+            hex!("175257a31eb09dea9366d8bb79ad80ba"),
+            hex!("6b26114b9cba2b63a9e8dd4f"),
+            hex!("9ddd12c994c0698b89374a9c077a3077"),
+        );
+        check(
+            QuicVersion::Draft29,
+            Side::Server,
+            hex!("149d0b1662ab871fbe63c49b5e655a5d"),
+            hex!("bab2b12a4c76016ace47856d"),
+            hex!("c0c499a65a60024a18a250974ea01dfa"),
+        );
+    }
+
+    // The client Initial in RFC 9001 appendix A.2 and RFC 9369 appendix A.2 uses packet
+    // number 2, the server Initial in appendix A.3 uses packet number 1.
+    #[test]
+    fn sample_packet_nonces() {
+        let client = QuicKeys::initial(QuicVersion::V1, Side::Client, &DCID);
+        assert_eq!(client.nonce(2).to_array(), hex!("fa044b2f42a3fd3b46fb255e"));
+        let server = QuicKeys::initial(QuicVersion::V1, Side::Server, &DCID);
+        assert_eq!(server.nonce(1).to_array(), hex!("0ac1493ca1905853b0bba03f"));
+
+        let client = QuicKeys::initial(QuicVersion::V2, Side::Client, &DCID);
+        assert_eq!(client.nonce(2).to_array(), hex!("91f73e2351d8fa91660e909d"));
+        let server = QuicKeys::initial(QuicVersion::V2, Side::Server, &DCID);
+        assert_eq!(server.nonce(1).to_array(), hex!("dd13c276499c0249d3310653"));
+    }
+}
@@ -0,0 +1,357 @@
+use crate::{ApiMisuse, Error};
+
+/// How [`reconstruct`] picks the full counter from its truncated low bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReconstructPolicy {
+    /// The value closest to `expected_next`, with ties going to the larger value, as in
+    /// RFC 9000 appendix A.3.
+    ///
+    /// This is right whenever the true value `v` satisfies
+    /// `expected_next - 2^(bits-1) < v <= expected_next + 2^(bits-1)`.
+    Closest,
+    /// The smallest value not below `expected_next`, for counters that never go back.
+    ///
+    /// This is right whenever `expected_next <= v < expected_next + 2^bits`.
+    Monotonic,
+}
+
+/// Rebuild a counter from its low `bits` bits, given the value expected next.
+///
+/// Returns `None` only for [`ReconstructPolicy::Monotonic`] when no such value fits in
+/// a `u64`.  Returns [`ApiMisuse::TruncatedWidth`] unless `bits` is between 1 and 63;
+/// send the whole value otherwise.
+pub fn reconstruct(
+    expected_next: u64,
+    truncated: u64,
+    bits: u32,
+    policy: ReconstructPolicy,
+) -> Result<Option<u64>, Error> {
+    if !(1..64).contains(&bits) {
+        return Err(ApiMisuse::TruncatedWidth { bits }.into());
+    }
+    Ok(reconstruct_inner(expected_next, truncated, bits, policy))
+}
+
+/// [`reconstruct`] for a `bits` already known to be between 1 and 63.
+fn reconstruct_inner(
+    expected_next: u64,
+    truncated: u64,
+    bits: u32,
+    policy: ReconstructPolicy,
+) -> Option<u64> {
+    let truncated = truncated & ((1 << bits) - 1);
+    match policy {
+        ReconstructPolicy::Closest => Some(closest(expected_next, truncated, bits, u64::MAX)),
+        ReconstructPolicy::Monotonic => {
+            let window = 1u64 << bits;
+            let candidate = (expected_next & !(window - 1)) | truncated;
+            if candidate < expected_next {
+                candidate.checked_add(window)
+            } else {
+                Some(candidate)
+            }
+        }
+    }
+}
+
+/// The value below `limit` with the given low `bits` that is closest to `expected`.
+pub(crate) fn closest(expected: u64, truncated: u64, bits: u32, limit: u64) -> u64 {
+    let window = 1u64 << bits;
+    let half = window / 2;
+    let candidate = (expected & !(window - 1)) | truncated;
+
+    if candidate.saturating_add(half) <= expected && candidate < limit - window {
+        candidate + window
+    } else if candidate > expected.saturating_add(half) && candidate >= window {
+        candidate - window
+    } else {
+        candidate
+    }
+}
+
+/// Rebuild the 64-bit ESP extended sequence number from the low 32 bits, RFC 4303
+/// appendix A.2.
+///
+/// `top` is the highest authenticated sequence number and `window` the size of the
+/// replay window; the result is the first value at or above the bottom of the window.
+pub fn reconstruct_esp_seq(top: u64, window: u32, seq_low: u32) -> Option<u64> {
+    let bottom = top.checked_add(1)?.saturating_sub(u64::from(window));
+    reconstruct_inner(bottom, u64::from(seq_low), 32, ReconstructPolicy::Monotonic)
+}
+
+/// Rebuild the 32-bit LoRaWAN frame counter from the 16-bit FCnt field.
+///
+/// `next_fcnt` is one more than the last accepted counter; counters never go back.
+pub fn reconstruct_lorawan_fcnt(next_fcnt: u32, fcnt: u16) -> Option<u32> {
+    reconstruct_inner(
+        u64::from(next_fcnt),
+        u64::from(fcnt),
+        16,
+        ReconstructPolicy::Monotonic,
+    )
+    .and_then(|v| u32::try_from(v).ok())
+}
+
+/// Rebuild the PDCP COUNT from a received `sn_bits`-bit SN (12 or 18), 3GPP TS 38.323
+/// section 5.2.2.
+///
+/// `rx_deliv` is the COUNT of the first PDCP SDU not yet delivered.  This is the
+/// closest value to `rx_deliv`, except that a tie goes to the smaller HFN.
+///
+/// Returns [`ApiMisuse::TruncatedWidth`] if `sn_bits` is not 12 or 18.
+pub fn reconstruct_pdcp_count(rx_deliv: u32, sn: u32, sn_bits: u32) -> Result<u32, Error> {
+    if sn_bits != 12 && sn_bits != 18 {
+        return Err(ApiMisuse::TruncatedWidth { bits: sn_bits }.into());
+    }
+    let mask = (1 << sn_bits) - 1;
+    let window = 1 << (sn_bits - 1);
+    let (hfn, deliv_sn) = (rx_deliv >> sn_bits, rx_deliv & mask);
+    let sn = sn & mask;
+
+    let hfn = if sn + window < deliv_sn {
+        hfn.wrapping_add(1)
+    } else if sn >= deliv_sn + window {
+        hfn.wrapping_sub(1)
+    } else {
+        hfn
+    };
+    Ok(hfn << sn_bits | sn)
+}
+
+/// Estimate the SRTP packet index from a received SEQ, RFC 3711 section 3.3.1.
+///
+/// `highest` is the highest authenticated index, that is the ROC and `s_l`.  Unlike
+/// [`ReconstructPolicy::Closest`], a tie keeps the ROC of `highest`, and an index
+/// before ROC 0 is given ROC 0.
+pub fn reconstruct_srtp_index(highest: u64, seq: u16) -> u64 {
+    let (roc, s_l) = (highest >> 16, highest & 0xffff);
+    let seq = u64::from(seq);
+
+    let v = if s_l < 0x8000 && seq > s_l + 0x8000 {
+        roc.saturating_sub(1)
+    } else if s_l >= 0x8000 && s_l - 0x8000 > seq {
+        roc + 1
+    } else {
+        roc
+    };
+    v << 16 | seq
+}
+
+/// Rebuild an AUTOSAR SecOC freshness value from its truncated low `bits` bits.
+///
+/// The result is the smallest value greater than `latest`, the last accepted value,
+/// or `None` if there is none.  Returns [`ApiMisuse::TruncatedWidth`] unless `bits` is
+/// between 1 and 63.
+pub fn reconstruct_secoc_freshness(
+    latest: u64,
+    truncated: u64,
+    bits: u32,
+) -> Result<Option<u64>, Error> {
+    match latest.checked_add(1) {
+        Some(next) => reconstruct(next, truncated, bits, ReconstructPolicy::Monotonic),
+        None => reconstruct(0, truncated, bits, ReconstructPolicy::Monotonic).map(|_| None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::test_util::XorShift;
+
+    #[test]
+    fn closest_within_half_window() {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        for bits in 1..64 {
+            let window = 1u64 << bits;
+            let half = window / 2;
+            for _ in 0..2_000 {
+                let expected = rng.next() >> (rng.next() % 64);
+                // any value with expected - half < v <= expected + half
+                let offset = rng.next() % window;
+                let Some(v) = (expected + 1)
+                    .checked_sub(half)
+                    .and_then(|low| low.checked_add(offset))
+                else {
+                    continue;
+                };
+                assert_eq!(
+                    reconstruct(expected, v, bits, ReconstructPolicy::Closest).unwrap(),
+                    Some(v),
+                    "expected {expected:#x} v {v:#x} bits {bits}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn monotonic_within_window() {
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        for bits in 1..64 {
+            let window = 1u64 << bits;
+            for _ in 0..2_000 {
+                let expected = rng.next() >> (rng.next() % 64);
+                let Some(v) = expected.checked_add(rng.next() % window) else {
+                    continue;
+                };
+                assert_eq!(
+                    reconstruct(expected, v, bits, ReconstructPolicy::Monotonic).unwrap(),
+                    Some(v),
+                    "expected {expected:#x} v {v:#x} bits {bits}"
+                );
+            }
+        }
+        assert_eq!(
+            reconstruct(u64::MAX, 0, 8, ReconstructPolicy::Monotonic).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn boundaries() {
+        use ReconstructPolicy::*;
+        // ties go to the larger value
+        assert_eq!(reconstruct(0x180, 0x00, 8, Closest).unwrap(), Some(0x200));
+        assert_eq!(reconstruct(0x17f, 0x00, 8, Closest).unwrap(), Some(0x100));
+        assert_eq!(reconstruct(0x10, 0x90, 8, Closest).unwrap(), Some(0x90));
+        assert_eq!(reconstruct(0x10, 0x91, 8, Closest).unwrap(), Some(0x91));
+        // no wrap below zero or above u64::MAX
+        assert_eq!(reconstruct(1, 0xff, 8, Closest).unwrap(), Some(0xff));
+        assert_eq!(
+            reconstruct(u64::MAX, 0x00, 8, Closest).unwrap(),
+            Some(u64::MAX - 0xff)
+        );
+        // only the low bits of truncated are used
+        assert_eq!(
+            reconstruct(0x100, 0x1ff, 8, Monotonic).unwrap(),
+            Some(0x1ff)
+        );
+        assert_eq!(
+            reconstruct(0x100, 0x2ff, 8, Monotonic).unwrap(),
+            Some(0x1ff)
+        );
+        assert_eq!(reconstruct(0x1ff, 0x00, 8, Monotonic).unwrap(), Some(0x200));
+        assert_eq!(reconstruct(0x1ff, 0xff, 8, Monotonic).unwrap(), Some(0x1ff));
+    }
+
+    #[test]
+    fn invalid_widths() {
+        for bits in [0, 64, 65, u32::MAX] {
+            for policy in [ReconstructPolicy::Closest, ReconstructPolicy::Monotonic] {
+                assert!(matches!(
+                    reconstruct(0, 0, bits, policy),
+                    Err(Error::Api(ApiMisuse::TruncatedWidth { bits: b })) if b == bits
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn esp() {
+        let w = 64;
+        // case A of RFC 4303 appendix A2.2, Tl >= W - 1
+        assert_eq!(
+            reconstruct_esp_seq(0x1_0000_0100, w, 0x100),
+            Some(0x1_0000_0100)
+        );
+        assert_eq!(
+            reconstruct_esp_seq(0x1_0000_0100, w, 0xc1),
+            Some(0x1_0000_00c1)
+        );
+        assert_eq!(
+            reconstruct_esp_seq(0x1_0000_0100, w, 0xc0),
+            Some(0x2_0000_00c0)
+        );
+        // case B, Tl < W - 1: low values stay in Th, high values belong to Th - 1
+        assert_eq!(
+            reconstruct_esp_seq(0x1_0000_0010, w, 0x20),
+            Some(0x1_0000_0020)
+        );
+        assert_eq!(
+            reconstruct_esp_seq(0x1_0000_0010, w, 0xffff_fff0),
+            Some(0xffff_fff0)
+        );
+        assert_eq!(
+            reconstruct_esp_seq(0x1_0000_0010, w, 0xffff_ffd0),
+            Some(0x1_ffff_ffd0)
+        );
+        assert_eq!(reconstruct_esp_seq(5, w, 3), Some(3));
+        assert_eq!(reconstruct_esp_seq(u64::MAX, w, 0), None);
+    }
+
+    #[test]
+    fn lorawan() {
+        assert_eq!(reconstruct_lorawan_fcnt(0, 0), Some(0));
+        assert_eq!(reconstruct_lorawan_fcnt(0xfffe, 0xffff), Some(0xffff));
+        assert_eq!(reconstruct_lorawan_fcnt(0x1_0000, 0x0000), Some(0x1_0000));
+        assert_eq!(reconstruct_lorawan_fcnt(0x1_fffe, 0x0001), Some(0x2_0001));
+        // a repeated counter is pushed into the next cycle rather than accepted
+        assert_eq!(reconstruct_lorawan_fcnt(0x1_0005, 0x0004), Some(0x2_0004));
+        assert_eq!(reconstruct_lorawan_fcnt(0xffff_fff0, 0x0001), None);
+    }
+
+    #[test]
+    fn pdcp() {
+        // 12-bit SN, window 2048
+        let deliv = 5 << 12 | 0x100;
+        assert_eq!(reconstruct_pdcp_count(deliv, 0x100, 12).unwrap(), deliv);
+        assert_eq!(
+            reconstruct_pdcp_count(deliv, 0x8ff, 12).unwrap(),
+            5 << 12 | 0x8ff
+        );
+        assert_eq!(
+            reconstruct_pdcp_count(deliv, 0x900, 12).unwrap(),
+            4 << 12 | 0x900
+        );
+        let deliv = 5 << 12 | 0xf00;
+        assert_eq!(
+            reconstruct_pdcp_count(deliv, 0x700, 12).unwrap(),
+            5 << 12 | 0x700
+        );
+        assert_eq!(
+            reconstruct_pdcp_count(deliv, 0x6ff, 12).unwrap(),
+            6 << 12 | 0x6ff
+        );
+        // 18-bit SN
+        let deliv = 1 << 18 | 0x3_fff0;
+        assert_eq!(
+            reconstruct_pdcp_count(deliv, 0x10, 18).unwrap(),
+            2 << 18 | 0x10
+        );
+        assert_eq!(reconstruct_pdcp_count(0, 0x3_ffff, 18).unwrap(), u32::MAX);
+        assert!(matches!(
+            reconstruct_pdcp_count(0, 0, 16),
+            Err(Error::Api(ApiMisuse::TruncatedWidth { bits: 16 }))
+        ));
+    }
+
+    #[test]
+    fn srtp() {
+        // s_l < 2^15: more than half ahead belongs to the previous ROC
+        assert_eq!(reconstruct_srtp_index(0x1_0100, 0x8100), 0x1_8100);
+        assert_eq!(reconstruct_srtp_index(0x1_0100, 0x8101), 0x0_8101);
+        // s_l >= 2^15: more than half behind belongs to the next ROC
+        assert_eq!(reconstruct_srtp_index(0x1_f000, 0x7000), 0x1_7000);
+        assert_eq!(reconstruct_srtp_index(0x1_f000, 0x6fff), 0x2_6fff);
+        // nothing before ROC 0
+        assert_eq!(reconstruct_srtp_index(0x0010, 0xfff0), 0xfff0);
+    }
+
+    #[test]
+    fn secoc() {
+        assert_eq!(
+            reconstruct_secoc_freshness(0x1234, 0x35, 8).unwrap(),
+            Some(0x1235)
+        );
+        assert_eq!(
+            reconstruct_secoc_freshness(0x1234, 0x34, 8).unwrap(),
+            Some(0x1334)
+        );
+        assert_eq!(
+            reconstruct_secoc_freshness(0x1234, 0x33, 8).unwrap(),
+            Some(0x1333)
+        );
+        assert_eq!(reconstruct_secoc_freshness(u64::MAX, 0, 8).unwrap(), None);
+        assert!(reconstruct_secoc_freshness(u64::MAX, 0, 64).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{ApiMisuse, Error};
+
+const WORD_BITS: u64 = u64::BITS as u64;
+
+/// An anti-replay window over received sequence numbers, using the word bitmap of
+/// RFC 6479.
+///
+/// A sequence number is accepted if it is newer than every committed one, or if it is
+/// within `width` of the newest and has not been committed before.  [`Self::check`]
+/// never changes the window; call [`Self::commit`] only once the packet has been
+/// authenticated, so forged packets cannot move the window forward.
+#[derive(Clone, Debug)]
+pub struct ReplayWindow {
+    width: u64,
+    top: Option<u64>,
+    bitmap: Vec<u64>,
+}
+
+impl ReplayWindow {
+    /// The largest supported width: 8 KiB of bitmap, well above the 8192 bits
+    /// WireGuard uses.
+    pub const MAX_WIDTH: u64 = 1 << 16;
+
+    /// Create a window accepting the newest sequence number and the `width - 1` before
+    /// it.
+    ///
+    /// Returns [`ApiMisuse::ReplayWindowWidth`] unless `width` is between 1 and
+    /// [`Self::MAX_WIDTH`].  The bitmap holds one word more than `width` needs, so that
+    /// moving the window forward only ever clears whole words.
+    pub fn new(width: u64) -> Result<Self, Error> {
+        if width == 0 || width > Self::MAX_WIDTH {
+            return Err(ApiMisuse::ReplayWindowWidth { width }.into());
+        }
+        let words = (width.div_ceil(WORD_BITS) + 1) as usize;
+        Ok(Self {
+            width,
+            top: None,
+            bitmap: vec![0; words],
+        })
+    }
+
+    /// Check whether `seq` would be accepted, without changing the window.
+    ///
+    /// Returns [`Error::Replay`] if `seq` was committed before, and
+    /// [`Error::OutsideWindow`] if it is too old to tell.
+    pub fn check(&self, seq: u64) -> Result<(), Error> {
+        let Some(top) = self.top else {
+            return Ok(());
+        };
+        if seq > top {
+            return Ok(());
+        }
+        if top - seq >= self.width {
+            return Err(Error::OutsideWindow { seq });
+        }
+        let (word, bit) = self.position(seq);
+        match self.bitmap[word] & bit {
+            0 => Ok(()),
+            _ => Err(Error::Replay { seq }),
+        }
+    }
+
+    /// Record `seq` as received, moving the window forward if it is the newest.
+    ///
+    /// Fails without changing the window if [`Self::check`] fails.
+    pub fn commit(&mut self, seq: u64) -> Result<(), Error> {
+        self.check(seq)?;
+
+        match self.top {
+            Some(top) if seq <= top => {}
+            Some(top) => {
+                let current = top / WORD_BITS;
+                let words = self.bitmap.len() as u64;
+                let shift = (seq / WORD_BITS - current).min(words);
+                for i in 1..=shift {
+                    self.bitmap[((current + i) % words) as usize] = 0;
+                }
+                self.top = Some(seq);
+            }
+            None => self.top = Some(seq),
+        }
+
+        let (word, bit) = self.position(seq);
+        self.bitmap[word] |= bit;
+        Ok(())
+    }
+
+    /// Return the newest committed sequence number.
+    pub fn top(&self) -> Option<u64> {
+        self.top
+    }
+
+    /// Return the number of sequence numbers the window covers.
+    pub fn width(&self) -> u64 {
+        self.width
+    }
+
+    fn position(&self, seq: u64) -> (usize, u64) {
+        let word = (seq / WORD_BITS) % self.bitmap.len() as u64;
+        (word as usize, 1 << (seq % WORD_BITS))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::test_util::XorShift;
+    use std::collections::HashSet;
+
+    /// The window as a set of every committed sequence number.
+    struct Model {
+        width: u64,
+        seen: HashSet<u64>,
+        top: Option<u64>,
+    }
+
+    impl Model {
+        fn check(&self, seq: u64) -> Result<(), ()> {
+            match self.top {
+                Some(top) if seq <= top && top - seq >= self.width => Err(()),
+                _ if self.seen.contains(&seq) => Err(()),
+                _ => Ok(()),
+            }
+        }
+
+        fn commit(&mut self, seq: u64) -> Result<(), ()> {
+            self.check(seq)?;
+            self.seen.insert(seq);
+            self.top = self.top.max(Some(seq));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn matches_model() {
+        for width in [1u64, 63, 64, 65, 100, 128, 1000, 4096, 8129] {
+            let mut rng = XorShift(width.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1);
+            let mut window = ReplayWindow::new(width).unwrap();
+            let mut model = Model {
+                width,
+                seen: HashSet::new(),
+                top: None,
+            };
+
+            let mut base = 0u64;
+            for _ in 0..20_000 {
+                let r = rng.next();
+                let seq = match r % 8 {
+                    // big jumps, sometimes past the whole bitmap
+                    0 => base + r % (4 * width + 256),
+                    // just around the window edge
+                    1 => (base + width).saturating_sub(r % 130),
+                    2 => base.saturating_sub(width).saturating_add(r % 3),
+                    _ => (base + width / 2).saturating_sub(r % (width + 64)),
+                };
+                let expected = model.check(seq);
+                assert_eq!(window.check(seq).is_ok(), expected.is_ok(), "check {seq}");
+                if r & 0x100 != 0 {
+                    assert_eq!(
+                        window.commit(seq).is_ok(),
+                        model.commit(seq).is_ok(),
+                        "commit {seq}"
+                    );
+                    base = model.top.unwrap_or(0);
+                }
+                assert_eq!(window.top(), model.top);
+            }
+        }
+    }
+
+    #[test]
+    fn check_does_not_advance() {
+        let mut window = ReplayWindow::new(64).unwrap();
+        window.commit(10).unwrap();
+        window.check(1_000_000).unwrap();
+        assert_eq!(window.top(), Some(10));
+        window.commit(9).unwrap();
+        assert!(matches!(window.check(9), Err(Error::Replay { seq: 9 })));
+        assert!(matches!(window.commit(9), Err(Error::Replay { seq: 9 })));
+    }
+
+    #[test]
+    fn edges() {
+        let mut window = ReplayWindow::new(64).unwrap();
+        window.commit(0).unwrap();
+        assert!(window.commit(0).is_err());
+        window.commit(63).unwrap();
+        // 0 is still the oldest sequence number inside the window
+        assert!(matches!(window.check(0), Err(Error::Replay { seq: 0 })));
+        window.commit(64).unwrap();
+        assert!(matches!(
+            window.check(0),
+            Err(Error::OutsideWindow { seq: 0 })
+        ));
+        window.check(1).unwrap();
+
+        // jumping by exactly the bitmap size clears every word
+        window.commit(64 + 128).unwrap();
+        window.check(64 + 128 - 63).unwrap();
+        assert!(window.check(64 + 128 - 64).is_err());
+
+        window.commit(u64::MAX).unwrap();
+        assert!(matches!(
+            window.commit(u64::MAX),
+            Err(Error::Replay { seq: u64::MAX })
+        ));
+        window.commit(u64::MAX - 63).unwrap();
+        assert!(window.check(u64::MAX - 64).is_err());
+
+        assert!(matches!(
+            ReplayWindow::new(0),
+            Err(Error::Api(ApiMisuse::ReplayWindowWidth { width: 0 }))
+        ));
+        ReplayWindow::new(ReplayWindow::MAX_WIDTH).unwrap();
+        for width in [ReplayWindow::MAX_WIDTH + 1, 1 << 60, u64::MAX] {
+            assert!(matches!(
+                ReplayWindow::new(width),
+                Err(Error::Api(ApiMisuse::ReplayWindowWidth { width: w })) if w == width
+            ));
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{Error, Iv, NONCE_LEN, Nonce};
+
+/// A counter that hands out a unique `Nonce` for every message sealed under one `Iv`.
+///
+/// Sequence numbers are issued in strictly increasing order starting from zero. Once the
+/// counter reaches the configured limit, [`Error::SequenceExhausted`] is returned and the
+/// counter never wraps.
+#[derive(Clone)]
+pub struct NonceSequence<const N: usize = NONCE_LEN> {
+    iv: Iv<N>,
+    next: u64,
+    limit: u64,
+}
+
+impl<const N: usize> NonceSequence<N> {
+    /// Limit for TLS, where the sequence number is a full `u64`.
+    pub const TLS_LIMIT: u64 = u64::MAX;
+
+    /// Limit for DTLS, where the sequence number is 48 bits.
+    pub const DTLS_LIMIT: u64 = 1 << 48;
+
+    /// Limit for QUIC, where packet numbers are in the range 0 to 2^62-1.
+    pub const QUIC_LIMIT: u64 = 1 << 62;
+
+    /// Create a new sequence for `iv` with the [`Self::TLS_LIMIT`].
+    pub fn new(iv: Iv<N>) -> Self {
+        Self::with_limit(iv, Self::TLS_LIMIT)
+    }
+
+    /// Create a new sequence for `iv` which issues sequence numbers below `limit`.
+    pub fn with_limit(iv: Iv<N>, limit: u64) -> Self {
+        Self { iv, next: 0, limit }
+    }
+
+    /// Create a sequence for `iv` which continues from the sequence number `next`.
+    pub(crate) fn continue_from(iv: Iv<N>, next: u64, limit: u64) -> Self {
+        Self { iv, next, limit }
+    }
+
+    /// Return the nonce for the next sequence number and advance the counter.
+    pub fn next_nonce(&mut self) -> Result<Nonce<N>, Error> {
+        if self.next >= self.limit {
+            return Err(Error::SequenceExhausted { limit: self.limit });
+        }
+        let nonce = Nonce::new(&self.iv, self.next);
+        self.next += 1;
+        Ok(nonce)
+    }
+
+    /// Return the sequence number the next nonce will be built from.
+    pub fn next_seq(&self) -> u64 {
+        self.next
+    }
+
+    /// Return the number of nonces that can still be issued.
+    pub fn remaining(&self) -> u64 {
+        self.limit - self.next
+    }
+
+    /// Return the sequence limit.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Return the `Iv` the nonces are built from.
+    pub fn iv(&self) -> &Iv<N> {
+        &self.iv
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn issues_in_order() {
+        let iv = Iv::new(hex!("6fac81d4f2c3bebe02b8b375"));
+        let mut seq = NonceSequence::new(iv.clone());
+
+        for i in 0..4 {
+            assert_eq!(seq.next_seq(), i);
+            assert_eq!(seq.next_nonce().unwrap(), Nonce::new(&iv, i));
+        }
+    }
+
+    #[test]
+    fn exhausts_at_limit() {
+        let iv = Iv::new(hex!("6fac81d4f2c3bebe02b8b375"));
+        let mut seq = NonceSequence::with_limit(iv, 2);
+
+        assert!(seq.next_nonce().is_ok());
+        assert!(seq.next_nonce().is_ok());
+        assert_eq!(seq.remaining(), 0);
+        assert!(matches!(
+            seq.next_nonce(),
+            Err(Error::SequenceExhausted { limit: 2 })
+        ));
+        assert_eq!(seq.next_seq(), 2);
+    }
+}
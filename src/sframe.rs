@@ -0,0 +1,324 @@
+use hkdf::Hkdf;
+use sha2::{Sha256, Sha512};
+
+use crate::{Error, Iv, Nonce};
+
+/// The SFrame cipher suites, RFC 9605 section 4.5.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SframeCipherSuite {
+    AesCtr128HmacSha256_80,
+    AesCtr128HmacSha256_64,
+    AesCtr128HmacSha256_32,
+    Aes128GcmSha256_128,
+    Aes256GcmSha512_128,
+}
+
+impl SframeCipherSuite {
+    /// Map a cipher suite identifier to the suite.
+    pub fn from_wire(value: u16) -> Option<Self> {
+        Some(match value {
+            0x0001 => Self::AesCtr128HmacSha256_80,
+            0x0002 => Self::AesCtr128HmacSha256_64,
+            0x0003 => Self::AesCtr128HmacSha256_32,
+            0x0004 => Self::Aes128GcmSha256_128,
+            0x0005 => Self::Aes256GcmSha512_128,
+            _ => return None,
+        })
+    }
+
+    /// Return the cipher suite identifier.
+    pub fn to_wire(self) -> u16 {
+        match self {
+            Self::AesCtr128HmacSha256_80 => 0x0001,
+            Self::AesCtr128HmacSha256_64 => 0x0002,
+            Self::AesCtr128HmacSha256_32 => 0x0003,
+            Self::Aes128GcmSha256_128 => 0x0004,
+            Self::Aes256GcmSha512_128 => 0x0005,
+        }
+    }
+
+    /// Return `Nk`, the length of `sframe_key`.
+    ///
+    /// For the AES-CTR suites this is the 16-byte encryption key followed by the 32-byte
+    /// HMAC key.
+    pub const fn key_len(self) -> usize {
+        match self {
+            Self::AesCtr128HmacSha256_80
+            | Self::AesCtr128HmacSha256_64
+            | Self::AesCtr128HmacSha256_32 => 48,
+            Self::Aes128GcmSha256_128 => 16,
+            Self::Aes256GcmSha512_128 => 32,
+        }
+    }
+
+    /// Return `Nt`, the length of the authentication tag.
+    pub const fn tag_len(self) -> usize {
+        match self {
+            Self::AesCtr128HmacSha256_80 => 10,
+            Self::AesCtr128HmacSha256_64 => 8,
+            Self::AesCtr128HmacSha256_32 => 4,
+            Self::Aes128GcmSha256_128 | Self::Aes256GcmSha512_128 => 16,
+        }
+    }
+}
+
+/// The `sframe_key` and `sframe_salt` of one KID, RFC 9605 section 4.4.2.
+#[derive(Clone)]
+pub struct SframeKeys {
+    suite: SframeCipherSuite,
+    key: [u8; 48],
+    salt: Iv,
+}
+
+impl SframeKeys {
+    /// Derive the keys for `kid` from its `base_key`.
+    pub fn derive(suite: SframeCipherSuite, kid: u64, base_key: &[u8]) -> Self {
+        let mut key = [0u8; 48];
+        let mut salt = [0u8; 12];
+        let expand = |label: &str, out: &mut [u8]| {
+            let mut info = Vec::with_capacity(label.len() + 10);
+            info.extend_from_slice(label.as_bytes());
+            info.extend_from_slice(&kid.to_be_bytes());
+            info.extend_from_slice(&suite.to_wire().to_be_bytes());
+            match suite {
+                SframeCipherSuite::Aes256GcmSha512_128 => {
+                    Hkdf::<Sha512>::new(Some(&[]), base_key).expand(&info, out)
+                }
+                _ => Hkdf::<Sha256>::new(Some(&[]), base_key).expand(&info, out),
+            }
+            .expect("key and salt lengths are valid HKDF lengths");
+        };
+        expand("SFrame 1.0 Secret key ", &mut key[..suite.key_len()]);
+        expand("SFrame 1.0 Secret salt ", &mut salt);
+
+        Self {
+            suite,
+            key,
+            salt: Iv::new(salt),
+        }
+    }
+
+    /// Return the nonce for counter `ctr`, which is `sframe_salt` XOR `ctr`.
+    pub fn nonce(&self, ctr: u64) -> Nonce {
+        Nonce::new(&self.salt, ctr)
+    }
+
+    /// Return `sframe_key`.
+    pub fn key(&self) -> &[u8] {
+        &self.key[..self.suite.key_len()]
+    }
+
+    /// Return `sframe_salt`.
+    pub fn salt(&self) -> &Iv {
+        &self.salt
+    }
+
+    /// Return the cipher suite the keys were derived for.
+    pub fn suite(&self) -> SframeCipherSuite {
+        self.suite
+    }
+}
+
+/// The SFrame header carrying the KID and CTR, RFC 9605 section 4.3.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SframeHeader {
+    pub kid: u64,
+    pub ctr: u64,
+}
+
+impl SframeHeader {
+    /// Encode the header, using the compact form for values below 8 and the minimal
+    /// number of bytes otherwise.
+    pub fn encode(&self) -> Vec<u8> {
+        let (kid_bits, kid_len) = Self::encode_value(self.kid);
+        let (ctr_bits, ctr_len) = Self::encode_value(self.ctr);
+        let mut out = Vec::with_capacity(1 + kid_len + ctr_len);
+        out.push(kid_bits << 4 | ctr_bits);
+        out.extend_from_slice(&self.kid.to_be_bytes()[8 - kid_len..]);
+        out.extend_from_slice(&self.ctr.to_be_bytes()[8 - ctr_len..]);
+        out
+    }
+
+    /// Parse the header at the start of `frame` and return it with the rest of the frame.
+    ///
+    /// Returns [`Error::NonMinimalEncoding`] if the KID or CTR is not in the form
+    /// [`Self::encode`] produces, so that every header has a single encoding.
+    pub fn parse(frame: &[u8]) -> Result<(Self, &[u8]), Error> {
+        let (config, rest) = frame.split_first().ok_or(Error::MessageTooShort {
+            expected: 1,
+            actual: 0,
+        })?;
+        let (kid, kid_len) = Self::parse_value(config >> 4, rest, frame.len())?;
+        let (ctr, ctr_len) = Self::parse_value(config & 0x0f, &rest[kid_len..], frame.len())?;
+        Ok((Self { kid, ctr }, &rest[kid_len + ctr_len..]))
+    }
+
+    /// Return the 4 header bits for `value` and the number of bytes that follow.
+    fn encode_value(value: u64) -> (u8, usize) {
+        match value {
+            0..8 => (value as u8, 0),
+            _ => {
+                let len = 8 - value.leading_zeros() as usize / 8;
+                (0x08 | (len - 1) as u8, len)
+            }
+        }
+    }
+
+    fn parse_value(bits: u8, rest: &[u8], frame_len: usize) -> Result<(u64, usize), Error> {
+        if bits & 0x08 == 0 {
+            return Ok((u64::from(bits), 0));
+        }
+        let len = usize::from(bits & 0x07) + 1;
+        let bytes = rest.get(..len).ok_or(Error::MessageTooShort {
+            expected: frame_len - rest.len() + len,
+            actual: frame_len,
+        })?;
+        let value = bytes.iter().fold(0, |v, b| (v << 8) | u64::from(*b));
+        if Self::encode_value(value) != (bits, len) {
+            return Err(Error::NonMinimalEncoding);
+        }
+        Ok((value, len))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use hex_literal::hex;
+
+    const BASE_KEY: [u8; 16] = hex!("000102030405060708090a0b0c0d0e0f");
+
+    #[test]
+    fn header_encoding() {
+        for (kid, ctr, encoded) in [
+            (0, 0, &hex!("00")[..]),
+            (0, 7, &hex!("07")),
+            (7, 0, &hex!("70")),
+            (0, 8, &hex!("0808")),
+            (8, 0, &hex!("8008")),
+            (0, 0xff, &hex!("08ff")),
+            (0, 0x100, &hex!("090100")),
+            (0, 0xffff, &hex!("09ffff")),
+            (0, 0x1_0000, &hex!("0a010000")),
+            (0xffff_ffff, 0, &hex!("b0ffffffff")),
+            (0x1_0000_0000, 1, &hex!("c10100000000")),
+            (0x123, 0x4567, &hex!("9901234567")),
+            (
+                u64::MAX,
+                u64::MAX,
+                &hex!("ffffffffffffffffffffffffffffffffff"),
+            ),
+        ] {
+            let header = SframeHeader { kid, ctr };
+            assert_eq!(header.encode(), encoded);
+
+            let mut frame = encoded.to_vec();
+            frame.extend_from_slice(b"ciphertext");
+            assert_eq!(
+                SframeHeader::parse(&frame).unwrap(),
+                (header, &b"ciphertext"[..])
+            );
+        }
+
+        assert!(matches!(
+            SframeHeader::parse(&hex!("990123")),
+            Err(Error::MessageTooShort {
+                expected: 5,
+                actual: 3
+            })
+        ));
+        assert!(SframeHeader::parse(&[]).is_err());
+    }
+
+    #[test]
+    fn rejects_non_minimal_headers() {
+        for frame in [
+            // values below 8 in the extended form
+            &hex!("0805")[..],
+            &hex!("8000")[..],
+            // a leading zero byte
+            &hex!("090001")[..],
+            &hex!("a00000ff")[..],
+            &hex!("0f00ffffffffffffff")[..],
+        ] {
+            assert!(
+                matches!(SframeHeader::parse(frame), Err(Error::NonMinimalEncoding)),
+                "{frame:02x?}"
+            );
+        }
+    }
+
+    // RFC 9605 appendix C.2, for KID 0x123 and CTR 0x4567
+    #[test]
+    fn rfc9605_keys_and_nonces() {
+        for (suite, key, salt, nonce) in [
+            (
+                SframeCipherSuite::AesCtr128HmacSha256_80,
+                &hex!(
+                    "3f7d9a7c83ae8e1c8a11ae695ab59314b367e359fadac7b9c46b2bc6f81f46e1"
+                    "6b96f0811868d59402b7e870102720b3"
+                )[..],
+                hex!("50b29329a04dc0f184ac3168"),
+                hex!("50b29329a04dc0f184ac740f"),
+            ),
+            (
+                SframeCipherSuite::AesCtr128HmacSha256_64,
+                &hex!(
+                    "e2ec5c797540310483b16bf6e7a570d2a27d192fe869c7ccd8584a8d9dab9154"
+                    "9fbe553f5113461ec6aa83bf3865553e"
+                ),
+                hex!("e68ac8dd3d02fbcd368c5577"),
+                hex!("e68ac8dd3d02fbcd368c1010"),
+            ),
+            (
+                SframeCipherSuite::AesCtr128HmacSha256_32,
+                &hex!(
+                    "2c5703089cbb8c583475e4fc461d97d18809df79b6d550f78eb6d50ffa80d892"
+                    "11d57909934f46f5405e38cd583c69fe"
+                ),
+                hex!("38c16e4f5159700c00c7f350"),
+                hex!("38c16e4f5159700c00c7b637"),
+            ),
+            (
+                SframeCipherSuite::Aes128GcmSha256_128,
+                &hex!("d34f547f4ca4f9a7447006fe7fcbf768"),
+                hex!("75234edefe07819026751816"),
+                hex!("75234edefe07819026755d71"),
+            ),
+            (
+                SframeCipherSuite::Aes256GcmSha512_128,
+                &hex!("d3e27b0d4a5ae9e55df01a70e6d4d28d969b246e2936f4b7a5d9b494da6b9633"),
+                hex!("84991c167b8cd23c93708ec7"),
+                hex!("84991c167b8cd23c9370cba0"),
+            ),
+        ] {
+            let keys = SframeKeys::derive(suite, 0x123, &BASE_KEY);
+            assert_eq!(keys.key(), key, "{suite:?}");
+            assert_eq!(keys.salt().as_ref(), &salt, "{suite:?}");
+            assert_eq!(keys.nonce(0x4567).to_array(), nonce, "{suite:?}");
+        }
+        assert_eq!(
+            SframeHeader {
+                kid: 0x123,
+                ctr: 0x4567
+            }
+            .encode(),
+            hex!("9901234567")
+        );
+    }
+
+    #[test]
+    fn nonce() {
+        let keys = SframeKeys::derive(SframeCipherSuite::Aes128GcmSha256_128, 0x123, &BASE_KEY);
+        assert_eq!(keys.nonce(0).as_bytes(), keys.salt().as_ref());
+
+        for value in 1..=0x0a {
+            assert_eq!(
+                SframeCipherSuite::from_wire(value).map(SframeCipherSuite::to_wire),
+                (value <= 5).then_some(value)
+            );
+        }
+        assert_eq!(SframeCipherSuite::AesCtr128HmacSha256_32.tag_len(), 4);
+    }
+}
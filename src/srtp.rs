@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+
+use crate::reconstruct::reconstruct_srtp_index;
+use crate::{ApiMisuse, Error, Iv, Nonce, xor};
+
+/// SRTP packet indices are 48 bits: the 32-bit ROC followed by the 16-bit SEQ.
+pub const SRTP_INDEX_LIMIT: u64 = 1 << 48;
+
+/// SRTCP indices are 31 bits, the top bit of their trailer word being the E-flag.
+pub const SRTCP_INDEX_LIMIT: u32 = 1 << 31;
+
+/// Return the SRTP AEAD nonce for `ssrc` and packet `index`, RFC 7714 section 8.1.
+///
+/// This is `salt ^ (0x0000 || SSRC || ROC || SEQ)`, where `index` is `ROC || SEQ`.
+pub fn srtp_nonce(salt: &Iv, ssrc: u32, index: u64) -> Result<Nonce, Error> {
+    if index >= SRTP_INDEX_LIMIT {
+        return Err(Error::SequenceExhausted {
+            limit: SRTP_INDEX_LIMIT,
+        });
+    }
+    Ok(Nonce::new(&ssrc_iv(salt, ssrc), index))
+}
+
+/// Return the SRTCP AEAD nonce for `ssrc` and `srtcp_index`, RFC 7714 section 9.1.
+///
+/// This is `salt ^ (0x0000 || SSRC || 0x0000 || 0 || SRTCP index)`; the E-flag is
+/// not part of the nonce.
+pub fn srtcp_nonce(salt: &Iv, ssrc: u32, srtcp_index: u32) -> Result<Nonce, Error> {
+    if srtcp_index >= SRTCP_INDEX_LIMIT {
+        return Err(ApiMisuse::SrtcpIndexExceedsWidth { srtcp_index }.into());
+    }
+    Ok(Nonce::new(&ssrc_iv(salt, ssrc), u64::from(srtcp_index)))
+}
+
+/// Encode the SRTCP trailer word: the E-flag followed by the 31-bit `srtcp_index`.
+pub fn encode_srtcp_index(srtcp_index: u32, encrypted: bool) -> Result<[u8; 4], Error> {
+    if srtcp_index >= SRTCP_INDEX_LIMIT {
+        return Err(ApiMisuse::SrtcpIndexExceedsWidth { srtcp_index }.into());
+    }
+    let e_flag = if encrypted { SRTCP_INDEX_LIMIT } else { 0 };
+    Ok((e_flag | srtcp_index).to_be_bytes())
+}
+
+/// Parse the SRTCP trailer word into the SRTCP index and the E-flag.
+pub fn parse_srtcp_index(word: [u8; 4]) -> (u32, bool) {
+    let word = u32::from_be_bytes(word);
+    (
+        word & (SRTCP_INDEX_LIMIT - 1),
+        word & SRTCP_INDEX_LIMIT != 0,
+    )
+}
+
+/// The salt with the SSRC folded into bytes 2 to 5.
+fn ssrc_iv(salt: &Iv, ssrc: u32) -> Iv {
+    let mut iv = salt.0;
+    xor(&mut iv[2..6], &ssrc.to_be_bytes());
+    Iv::new(iv)
+}
+
+/// SRTP nonces for every SSRC of a session, tracking each stream's rollover counter.
+///
+/// For each SSRC this keeps the highest packet index, that is the ROC and the highest
+/// SEQ `s_l`, and estimates the index of a packet from its SEQ with
+/// [`reconstruct_srtp_index`].  The state only moves forward through
+/// [`Self::next_nonce`] on the sending side, or [`Self::commit`] once a received packet
+/// has been authenticated.
+#[derive(Clone)]
+pub struct SrtpContext {
+    salt: Iv,
+    streams: BTreeMap<u32, u64>,
+}
+
+impl SrtpContext {
+    /// Create a context for the session `salt` with no streams recorded yet.
+    pub fn new(salt: Iv) -> Self {
+        Self {
+            salt,
+            streams: BTreeMap::new(),
+        }
+    }
+
+    /// Estimate the packet index of a packet from `ssrc` with sequence number `seq`.
+    ///
+    /// The first packet of a stream has ROC 0 unless [`Self::set_roc`] says otherwise.
+    /// A packet that would belong before ROC 0 is given ROC 0.
+    pub fn estimate_index(&self, ssrc: u32, seq: u16) -> u64 {
+        match self.streams.get(&ssrc) {
+            Some(&highest) => reconstruct_srtp_index(highest, seq),
+            None => u64::from(seq),
+        }
+    }
+
+    /// Return the packet index and nonce for sending `seq` on `ssrc`, and record it.
+    ///
+    /// Returns [`Error::SequenceExhausted`] once the ROC would pass 2^32-1.
+    pub fn next_nonce(&mut self, ssrc: u32, seq: u16) -> Result<(u64, Nonce), Error> {
+        let (index, nonce) = self.nonce_for(ssrc, seq)?;
+        self.commit(ssrc, index)?;
+        Ok((index, nonce))
+    }
+
+    /// Return the estimated packet index and nonce for a packet received from `ssrc`,
+    /// without recording it.
+    pub fn nonce_for(&self, ssrc: u32, seq: u16) -> Result<(u64, Nonce), Error> {
+        let index = self.estimate_index(ssrc, seq);
+        Ok((index, srtp_nonce(&self.salt, ssrc, index)?))
+    }
+
+    /// Record packet `index` of `ssrc` after it was authenticated.
+    ///
+    /// This moves the ROC and `s_l` forward if `index` is the highest seen.  Returns
+    /// [`Error::SequenceExhausted`] without recording anything if `index` is not below
+    /// [`SRTP_INDEX_LIMIT`].
+    pub fn commit(&mut self, ssrc: u32, index: u64) -> Result<(), Error> {
+        if index >= SRTP_INDEX_LIMIT {
+            return Err(Error::SequenceExhausted {
+                limit: SRTP_INDEX_LIMIT,
+            });
+        }
+        let highest = self.streams.entry(ssrc).or_insert(index);
+        *highest = (*highest).max(index);
+        Ok(())
+    }
+
+    /// Set the ROC of `ssrc` and its highest sequence number `seq`, for a receiver
+    /// joining a stream whose ROC was signalled out of band.
+    pub fn set_roc(&mut self, ssrc: u32, roc: u32, seq: u16) {
+        self.streams
+            .insert(ssrc, u64::from(roc) << 16 | u64::from(seq));
+    }
+
+    /// Return the ROC of `ssrc`, if any packet of it has been recorded.
+    pub fn roc(&self, ssrc: u32) -> Option<u32> {
+        self.streams
+            .get(&ssrc)
+            .map(|highest| (highest >> 16) as u32)
+    }
+
+    /// Return the SRTCP nonce for `ssrc` and `srtcp_index`, as [`srtcp_nonce`].
+    pub fn srtcp_nonce(&self, ssrc: u32, srtcp_index: u32) -> Result<Nonce, Error> {
+        srtcp_nonce(&self.salt, ssrc, srtcp_index)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::aead::{AeadCipher, AeadKey};
+    use hex_literal::hex;
+
+    const KEY: [u8; 16] = hex!("000102030405060708090a0b0c0d0e0f");
+    const SALT: [u8; 12] = hex!("517569642070726f2071756f");
+
+    // RFC 7714 section 16.1.1, AEAD_AES_128_GCM encryption
+    #[test]
+    fn rfc7714_srtp() {
+        let header = hex!("8040f17b8041f8d35501a0b2");
+        let mut context = SrtpContext::new(Iv::new(SALT));
+        let (index, nonce) = context.next_nonce(0x5501a0b2, 0xf17b).unwrap();
+        assert_eq!(index, 0xf17b);
+        assert_eq!(nonce.to_array(), hex!("51753c6580c2726f20718414"));
+
+        let mut payload = *b"Gallia est omnis divisa in partes tres";
+        let tag =
+            AeadKey::new(AeadCipher::Aes128Gcm, &KEY)
+                .unwrap()
+                .seal(&nonce, &header, &mut payload);
+        assert_eq!(
+            payload,
+            hex!(
+                "f24de3a3fb34de6cacba861c9d7e4bcabe633bd50d294e6f42a5f47a51c7d19b"
+                "36de3adf8833"
+            )
+        );
+        assert_eq!(tag, hex!("899d7f27beb16a9152cf765ee4390cce"));
+    }
+
+    // RFC 7714 section 17.1, AEAD_AES_128_GCM SRTCP encryption
+    #[test]
+    fn rfc7714_srtcp() {
+        let salt = Iv::new(SALT);
+        let nonce = srtcp_nonce(&salt, 0x4d617273, 0x5d4).unwrap();
+        assert_eq!(nonce.to_array(), hex!("517524055203726f207170bb"));
+
+        // the RTCP header and the SRTCP index word
+        let mut aad = hex!("81c8000d4d617273").to_vec();
+        aad.extend_from_slice(&encode_srtcp_index(0x5d4, true).unwrap());
+        let mut payload = hex!(
+            "4e5450314e545032525450200000042a0000e9304c756e61deadbeefdeadbeef"
+            "deadbeefdeadbeefdeadbeef"
+        );
+        let tag =
+            AeadKey::new(AeadCipher::Aes128Gcm, &KEY)
+                .unwrap()
+                .seal(&nonce, &aad, &mut payload);
+        assert_eq!(
+            payload,
+            hex!(
+                "63e94885dcdab67ca727d7662f6b7e997ff5c0f76c06f32dc676a5f1730d6fda"
+                "4ce09b4686303ded0bb9275b"
+            )
+        );
+        assert_eq!(tag, hex!("c84aa45896cf4d2fc5abf87245d9eade"));
+    }
+
+    #[test]
+    fn srtcp_index() {
+        let salt = Iv::new(SALT);
+        assert_eq!(encode_srtcp_index(0x5d4, true).unwrap(), hex!("800005d4"));
+        assert_eq!(encode_srtcp_index(0x5d4, false).unwrap(), hex!("000005d4"));
+        assert_eq!(parse_srtcp_index(hex!("800005d4")), (0x5d4, true));
+        assert_eq!(parse_srtcp_index(hex!("7fffffff")), (0x7fff_ffff, false));
+        assert!(matches!(
+            srtcp_nonce(&salt, 0, SRTCP_INDEX_LIMIT),
+            Err(Error::Api(ApiMisuse::SrtcpIndexExceedsWidth { .. }))
+        ));
+        assert!(encode_srtcp_index(SRTCP_INDEX_LIMIT, true).is_err());
+    }
+
+    #[test]
+    fn roc_estimation() {
+        let mut context = SrtpContext::new(Iv::new(SALT));
+        let ssrc = 1;
+        context.commit(ssrc, 0x1_0100).unwrap();
+        // s_l < 2^15: far ahead belongs to the previous ROC
+        assert_eq!(context.estimate_index(ssrc, 0x8100), 0x1_8100);
+        assert_eq!(context.estimate_index(ssrc, 0x8101), 0x0_8101);
+        assert_eq!(context.estimate_index(ssrc, 0x0000), 0x1_0000);
+
+        // s_l >= 2^15: far behind belongs to the next ROC
+        context.commit(ssrc, 0x1_f000).unwrap();
+        assert_eq!(context.estimate_index(ssrc, 0x7000), 0x1_7000);
+        assert_eq!(context.estimate_index(ssrc, 0x6fff), 0x2_6fff);
+        assert_eq!(context.estimate_index(ssrc, 0xffff), 0x1_ffff);
+
+        // receiving does not move the state until commit
+        let (index, _) = context.nonce_for(ssrc, 0x0005).unwrap();
+        assert_eq!(index, 0x2_0005);
+        assert_eq!(context.roc(ssrc), Some(1));
+        context.commit(ssrc, index).unwrap();
+        assert_eq!(context.roc(ssrc), Some(2));
+        // a late packet from before the wrap does not move it back
+        context.commit(ssrc, 0x1_fff0).unwrap();
+        assert_eq!(context.estimate_index(ssrc, 0xfff0), 0x1_fff0);
+        assert_eq!(context.roc(ssrc), Some(2));
+
+        // nothing before ROC 0, and streams are independent
+        context.set_roc(2, 0, 0x0010);
+        assert_eq!(context.estimate_index(2, 0xfff0), 0xfff0);
+        assert_eq!(context.roc(3), None);
+        assert_eq!(context.estimate_index(3, 0xfff0), 0xfff0);
+    }
+
+    #[test]
+    fn sender_wraps() {
+        let salt = Iv::new(SALT);
+        let mut context = SrtpContext::new(salt.clone());
+        let mut seq = 0xfffeu16;
+        for expected in [0xfffe, 0xffff, 0x1_0000, 0x1_0001] {
+            let (index, nonce) = context.next_nonce(7, seq).unwrap();
+            assert_eq!(index, expected);
+            assert_eq!(nonce, srtp_nonce(&salt, 7, index).unwrap());
+            seq = seq.wrapping_add(1);
+        }
+        assert_eq!(context.roc(7), Some(1));
+
+        context.set_roc(7, u32::MAX, 0xffff);
+        assert!(matches!(
+            context.next_nonce(7, 0),
+            Err(Error::SequenceExhausted {
+                limit: SRTP_INDEX_LIMIT
+            })
+        ));
+        assert_eq!(context.roc(7), Some(u32::MAX));
+
+        // an index past 48 bits is never recorded
+        assert!(matches!(
+            context.commit(7, SRTP_INDEX_LIMIT),
+            Err(Error::SequenceExhausted {
+                limit: SRTP_INDEX_LIMIT
+            })
+        ));
+        assert_eq!(context.roc(7), Some(u32::MAX));
+        assert_eq!(context.estimate_index(7, 0xffff), SRTP_INDEX_LIMIT - 1);
+    }
+}
@@ -0,0 +1,12 @@
+/// A xorshift64 generator for the property tests, so they are reproducible without a
+/// dependency on `rand`.
+pub(crate) struct XorShift(pub(crate) u64);
+
+impl XorShift {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
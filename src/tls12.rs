@@ -0,0 +1,176 @@
+use crate::{ApiMisuse, Error, Iv, NONCE_LEN, Nonce};
+
+/// Length of the explicit nonce carried in TLS 1.2 AES-GCM and AES-CCM records.
+pub const TLS12_EXPLICIT_NONCE_LEN: usize = 8;
+
+/// The TLS 1.2 AEAD families, which differ in how the nonce is built.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tls12Aead {
+    /// AES-GCM, RFC 5288: a 4-byte implicit salt followed by an 8-byte explicit nonce.
+    AesGcm,
+    /// AES-CCM, RFC 6655: built the same way as [`Self::AesGcm`].
+    AesCcm,
+    /// ChaCha20-Poly1305, RFC 7905: a 12-byte IV XORed with the sequence number.
+    ChaCha20Poly1305,
+}
+
+impl Tls12Aead {
+    /// Return the length of the fixed IV taken from the key block.
+    pub const fn fixed_iv_len(self) -> usize {
+        match self {
+            Self::AesGcm | Self::AesCcm => 4,
+            Self::ChaCha20Poly1305 => NONCE_LEN,
+        }
+    }
+
+    /// Return the length of the explicit nonce at the start of each record fragment.
+    pub const fn explicit_nonce_len(self) -> usize {
+        match self {
+            Self::AesGcm | Self::AesCcm => TLS12_EXPLICIT_NONCE_LEN,
+            Self::ChaCha20Poly1305 => 0,
+        }
+    }
+}
+
+/// Nonces for one direction of a TLS 1.2 connection.
+///
+/// For AES-GCM and AES-CCM the 4-byte salt is kept as an `Iv` with eight trailing zero
+/// bytes, so that `iv ^ explicit` is `salt || explicit`.  The explicit nonce is the
+/// record sequence number.
+#[derive(Clone)]
+pub struct Tls12Nonces {
+    aead: Tls12Aead,
+    iv: Iv,
+}
+
+impl Tls12Nonces {
+    /// Create the nonces for `aead` from the fixed IV in the key block.
+    pub fn new(aead: Tls12Aead, fixed_iv: &[u8]) -> Result<Self, Error> {
+        if fixed_iv.len() != aead.fixed_iv_len() {
+            return Err(ApiMisuse::IvLengthMismatch {
+                expected: aead.fixed_iv_len(),
+                actual: fixed_iv.len(),
+            }
+            .into());
+        }
+        let mut iv = [0u8; NONCE_LEN];
+        iv[..fixed_iv.len()].copy_from_slice(fixed_iv);
+        Ok(Self {
+            aead,
+            iv: Iv::new(iv),
+        })
+    }
+
+    /// Return the nonce for sealing record `seq`.
+    ///
+    /// The explicit nonce, if any, is written to the start of `fragment` and the number
+    /// of bytes written is returned.
+    pub fn seal_nonce(&self, seq: u64, fragment: &mut [u8]) -> Result<(Nonce, usize), Error> {
+        let written = match self.aead.explicit_nonce_len() {
+            0 => 0,
+            _ => write_explicit_nonce(seq, fragment)?,
+        };
+        Ok((Nonce::new(&self.iv, seq), written))
+    }
+
+    /// Return the nonce for opening record `seq`, and the ciphertext that follows the
+    /// explicit nonce.
+    pub fn open_nonce<'a>(&self, seq: u64, fragment: &'a [u8]) -> Result<(Nonce, &'a [u8]), Error> {
+        match self.aead.explicit_nonce_len() {
+            0 => Ok((Nonce::new(&self.iv, seq), fragment)),
+            _ => {
+                let (explicit, ciphertext) = parse_explicit_nonce(fragment)?;
+                Ok((Nonce::new(&self.iv, explicit), ciphertext))
+            }
+        }
+    }
+
+    /// Return the `Iv`, which is the salt followed by eight zero bytes for AES-GCM and
+    /// AES-CCM.
+    pub fn iv(&self) -> &Iv {
+        &self.iv
+    }
+
+    /// Return the AEAD family.
+    pub fn aead(&self) -> Tls12Aead {
+        self.aead
+    }
+}
+
+/// Write `explicit` as the big-endian explicit nonce at the start of `out`.
+pub fn write_explicit_nonce(explicit: u64, out: &mut [u8]) -> Result<usize, Error> {
+    let Some(out) = out.get_mut(..TLS12_EXPLICIT_NONCE_LEN) else {
+        return Err(Error::MessageTooShort {
+            expected: TLS12_EXPLICIT_NONCE_LEN,
+            actual: out.len(),
+        });
+    };
+    out.copy_from_slice(&explicit.to_be_bytes());
+    Ok(TLS12_EXPLICIT_NONCE_LEN)
+}
+
+/// Split a record fragment into its explicit nonce and the ciphertext that follows it.
+pub fn parse_explicit_nonce(fragment: &[u8]) -> Result<(u64, &[u8]), Error> {
+    let Some((explicit, rest)) = fragment.split_first_chunk::<TLS12_EXPLICIT_NONCE_LEN>() else {
+        return Err(Error::MessageTooShort {
+            expected: TLS12_EXPLICIT_NONCE_LEN,
+            actual: fragment.len(),
+        });
+    };
+    Ok((u64::from_be_bytes(*explicit), rest))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn rfc5288_explicit_nonce() {
+        let nonces = Tls12Nonces::new(Tls12Aead::AesGcm, &hex!("c0ffee01")).unwrap();
+
+        let mut fragment = [0u8; 24];
+        let (nonce, written) = nonces
+            .seal_nonce(0x0102_0304_0506_0708, &mut fragment)
+            .unwrap();
+        assert_eq!(written, 8);
+        assert_eq!(nonce.to_array(), hex!("c0ffee010102030405060708"));
+        assert_eq!(&fragment[..8], &hex!("0102030405060708"));
+
+        let (opened, ciphertext) = nonces.open_nonce(99, &fragment).unwrap();
+        assert_eq!(opened, nonce);
+        assert_eq!(ciphertext.len(), 16);
+    }
+
+    #[test]
+    fn rfc7905_xor() {
+        let iv = hex!("6fac81d4f2c3bebe02b8b375");
+        let nonces = Tls12Nonces::new(Tls12Aead::ChaCha20Poly1305, &iv).unwrap();
+
+        let mut fragment = [0u8; 16];
+        let (nonce, written) = nonces.seal_nonce(1, &mut fragment).unwrap();
+        assert_eq!(written, 0);
+        assert_eq!(nonce.to_array(), hex!("6fac81d4f2c3bebe02b8b374"));
+
+        let (opened, ciphertext) = nonces.open_nonce(1, &fragment).unwrap();
+        assert_eq!(opened, nonce);
+        assert_eq!(ciphertext, &fragment);
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(Tls12Nonces::new(Tls12Aead::AesCcm, &[0; 12]).is_err());
+        assert!(Tls12Nonces::new(Tls12Aead::ChaCha20Poly1305, &[0; 4]).is_err());
+
+        let nonces = Tls12Nonces::new(Tls12Aead::AesCcm, &[0; 4]).unwrap();
+        assert!(matches!(
+            nonces.open_nonce(0, &[0; 7]),
+            Err(Error::MessageTooShort {
+                expected: 8,
+                actual: 7
+            })
+        ));
+        assert!(nonces.seal_nonce(0, &mut [0; 7]).is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use hkdf::Hkdf;
+use sha2::{Sha256, Sha384};
+
+use crate::{ApiMisuse, Error, Iv};
+
+/// The hash function of a TLS 1.3 cipher suite.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha384,
+}
+
+impl HashAlgorithm {
+    /// Return the hash output length, which is also the length of a traffic secret.
+    pub const fn output_len(self) -> usize {
+        match self {
+            Self::Sha256 => 32,
+            Self::Sha384 => 48,
+        }
+    }
+}
+
+/// HKDF-Expand-Label from RFC 8446 section 7.1, with `prefix` prepended to `label`.
+///
+/// TLS 1.3 uses the `b"tls13 "` prefix.  `secret` must be exactly
+/// [`HashAlgorithm::output_len`] bytes.
+pub(crate) fn hkdf_expand_label(
+    hash: HashAlgorithm,
+    secret: &[u8],
+    prefix: &[u8],
+    label: &[u8],
+    context: &[u8],
+    out: &mut [u8],
+) -> Result<(), Error> {
+    if secret.len() != hash.output_len() {
+        return Err(ApiMisuse::SecretLengthMismatch {
+            expected: hash.output_len(),
+            actual: secret.len(),
+        }
+        .into());
+    }
+
+    let out_len = u16::try_from(out.len())
+        .expect("output length fits in u16")
+        .to_be_bytes();
+    let label_len = [u8::try_from(prefix.len() + label.len()).expect("label fits in u8")];
+    let context_len = [u8::try_from(context.len()).expect("context fits in u8")];
+    let info: [&[u8]; 6] = [&out_len, &label_len, prefix, label, &context_len, context];
+
+    match hash {
+        HashAlgorithm::Sha256 => Hkdf::<Sha256>::from_prk(secret)
+            .expect("secret length checked")
+            .expand_multi_info(&info, out),
+        HashAlgorithm::Sha384 => Hkdf::<Sha384>::from_prk(secret)
+            .expect("secret length checked")
+            .expand_multi_info(&info, out),
+    }
+    .expect("output length is within 255 * hash length");
+    Ok(())
+}
+
+impl<const N: usize> Iv<N> {
+    /// Derive the write IV from a TLS 1.3 traffic secret.
+    ///
+    /// This is `HKDF-Expand-Label(secret, "iv", "", N)` from RFC 8446 section 7.3.
+    pub fn from_tls13_secret(hash: HashAlgorithm, secret: &[u8]) -> Result<Self, Error> {
+        let mut iv = [0u8; N];
+        hkdf_expand_label(hash, secret, b"tls13 ", b"iv", &[], &mut iv)?;
+        Ok(Self::new(iv))
+    }
+}
+
+/// Derive the write key from a TLS 1.3 traffic secret.
+///
+/// This is `HKDF-Expand-Label(secret, "key", "", K)` from RFC 8446 section 7.3.
+pub fn tls13_write_key<const K: usize>(
+    hash: HashAlgorithm,
+    secret: &[u8],
+) -> Result<[u8; K], Error> {
+    let mut key = [0u8; K];
+    hkdf_expand_label(hash, secret, b"tls13 ", b"key", &[], &mut key)?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::Nonce;
+    use hex_literal::hex;
+    use hkdf::hmac::{Hmac, Mac};
+
+    // RFC 8448 section 3, simple 1-RTT handshake
+    const VECTORS: [([u8; 32], [u8; 16], [u8; 12]); 4] = [
+        (
+            // server handshake traffic secret
+            hex!("b67b7d690cc16c4e75e54213cb2d37b4e9c912bcded9105d42befd59d391ad38"),
+            hex!("3fce516009c21727d0f2e4e86ee403bc"),
+            hex!("5d313eb2671276ee13000b30"),
+        ),
+        (
+            // client handshake traffic secret
+            hex!("b3eddb126e067f35a780b3abf45e2d8f3b1a950738f52e9600746a0e27a55a21"),
+            hex!("dbfaa693d1762c5b666af5d950258d01"),
+            hex!("5bd3c71b836e0b76bb73265f"),
+        ),
+        (
+            // server application traffic secret
+            hex!("a11af9f05531f856ad47116b45a950328204b4f44bfb6b3a4b4f1f3fcb631643"),
+            hex!("9f02283b6c9c07efc26bb9f2ac92e356"),
+            hex!("cf782b88dd83549aadf1e984"),
+        ),
+        (
+            // client application traffic secret
+            hex!("9e40646ce79a7f9dc05af8889bce6552875afa0b06df0087f792ebb7c17504a5"),
+            hex!("17422dda596ed5d9acd890e3c63f5051"),
+            hex!("5b78923dee08579033e523d9"),
+        ),
+    ];
+
+    #[test]
+    fn rfc8448() {
+        for (secret, key, iv) in VECTORS {
+            let derived = Iv::<12>::from_tls13_secret(HashAlgorithm::Sha256, &secret).unwrap();
+            assert_eq!(derived.as_ref(), &iv);
+            assert_eq!(
+                tls13_write_key::<16>(HashAlgorithm::Sha256, &secret).unwrap(),
+                key
+            );
+
+            let mut nonce = iv;
+            nonce[11] ^= 1;
+            assert_eq!(Nonce::new(&derived, 1).to_array(), nonce);
+        }
+    }
+
+    #[test]
+    fn sha384_single_block() {
+        let secret = [0x5a; 48];
+
+        // HKDF-Expand for one block is HMAC(secret, info || 0x01)
+        let mut mac = Hmac::<Sha384>::new_from_slice(&secret).unwrap();
+        mac.update(&hex!("000c"));
+        mac.update(b"\x08tls13 iv\x00\x01");
+        let expected = mac.finalize().into_bytes();
+
+        let iv = Iv::<12>::from_tls13_secret(HashAlgorithm::Sha384, &secret).unwrap();
+        assert_eq!(iv.as_ref(), &expected[..12]);
+    }
+
+    #[test]
+    fn secret_length() {
+        assert!(matches!(
+            Iv::<12>::from_tls13_secret(HashAlgorithm::Sha384, &[0; 32]),
+            Err(Error::Api(ApiMisuse::SecretLengthMismatch {
+                expected: 48,
+                actual: 32
+            }))
+        ));
+    }
+}
@@ -0,0 +1,216 @@
+use crate::{Error, NoiseCipher, Nonce, ReplayWindow};
+
+/// Messages after which the initiator starts a new handshake.
+pub const REKEY_AFTER_MESSAGES: u64 = 1 << 60;
+
+/// Counters at or above this value are never sent or accepted.
+pub const REJECT_AFTER_MESSAGES: u64 = u64::MAX - (1 << 13);
+
+/// Return the ChaCha20-Poly1305 nonce for a transport data `counter`, which is 4 zero
+/// bytes followed by the little-endian counter.
+pub fn wireguard_nonce(counter: u64) -> Nonce {
+    NoiseCipher::ChaChaPoly.nonce(counter)
+}
+
+/// The sending counter of a WireGuard session.
+#[derive(Clone, Debug, Default)]
+pub struct WireGuardSender {
+    counter: u64,
+}
+
+impl WireGuardSender {
+    /// Start the counter at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return the on-wire counter field and the nonce for the next message.
+    ///
+    /// Returns [`Error::SequenceExhausted`] once [`REJECT_AFTER_MESSAGES`] is reached.
+    pub fn next_counter(&mut self) -> Result<([u8; 8], Nonce), Error> {
+        if self.counter >= REJECT_AFTER_MESSAGES {
+            return Err(Error::SequenceExhausted {
+                limit: REJECT_AFTER_MESSAGES,
+            });
+        }
+        let counter = self.counter;
+        self.counter += 1;
+        Ok((counter.to_le_bytes(), wireguard_nonce(counter)))
+    }
+
+    /// Return whether [`REKEY_AFTER_MESSAGES`] have been sent.
+    pub fn needs_rekey(&self) -> bool {
+        self.counter >= REKEY_AFTER_MESSAGES
+    }
+
+    /// Return the next counter.
+    pub fn counter(&self) -> u64 {
+        self.counter
+    }
+}
+
+/// The receiving side of a WireGuard session.
+///
+/// Counters are accepted out of order, but only once and only within
+/// [`Self::WINDOW`] of the greatest counter seen.
+#[derive(Clone, Debug)]
+pub struct WireGuardReceiver {
+    window: ReplayWindow,
+}
+
+impl WireGuardReceiver {
+    /// How far behind the greatest counter a message may be, as in the Linux driver's
+    /// 8192-bit bitmap.
+    pub const WINDOW: u64 = 8192 - 64;
+
+    /// Create a receiver that has not accepted any message yet.
+    pub fn new() -> Self {
+        Self {
+            window: ReplayWindow::new(Self::WINDOW + 1).expect("window width is valid"),
+        }
+    }
+
+    /// Parse the on-wire counter field and return the counter and its nonce.
+    pub fn nonce(&self, wire: [u8; 8]) -> Result<(u64, Nonce), Error> {
+        let counter = u64::from_le_bytes(wire);
+        if counter >= REJECT_AFTER_MESSAGES {
+            return Err(Error::OutsideWindow { seq: counter });
+        }
+        Ok((counter, wireguard_nonce(counter)))
+    }
+
+    /// Check whether `counter` would be accepted, without recording it.
+    pub fn check(&self, counter: u64) -> Result<(), Error> {
+        if counter >= REJECT_AFTER_MESSAGES {
+            return Err(Error::OutsideWindow { seq: counter });
+        }
+        self.window.check(counter)
+    }
+
+    /// Record `counter` after its message was authenticated.
+    ///
+    /// Returns [`Error::Replay`] if it was accepted before, and [`Error::OutsideWindow`]
+    /// if it is too old or not below [`REJECT_AFTER_MESSAGES`].
+    pub fn accept(&mut self, counter: u64) -> Result<(), Error> {
+        if counter >= REJECT_AFTER_MESSAGES {
+            return Err(Error::OutsideWindow { seq: counter });
+        }
+        self.window.commit(counter)
+    }
+}
+
+impl Default for WireGuardReceiver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn sender() {
+        let mut sender = WireGuardSender::new();
+        assert_eq!(sender.next_counter().unwrap(), ([0; 8], Nonce([0; 12])));
+        let (wire, nonce) = sender.next_counter().unwrap();
+        assert_eq!(wire, hex!("0100000000000000"));
+        assert_eq!(nonce.to_array(), hex!("000000000100000000000000"));
+        assert!(!sender.needs_rekey());
+
+        sender.counter = REKEY_AFTER_MESSAGES;
+        assert!(sender.needs_rekey());
+        sender.counter = REJECT_AFTER_MESSAGES - 1;
+        let (wire, _) = sender.next_counter().unwrap();
+        assert_eq!(u64::from_le_bytes(wire), REJECT_AFTER_MESSAGES - 1);
+        assert!(matches!(
+            sender.next_counter(),
+            Err(Error::SequenceExhausted { .. })
+        ));
+
+        let receiver = WireGuardReceiver::new();
+        assert_eq!(
+            receiver.nonce(wire).unwrap().1,
+            wireguard_nonce(u64::from_le_bytes(wire))
+        );
+        assert!(receiver.nonce([0xff; 8]).is_err());
+    }
+
+    // The sequence from the counter selftest in the Linux WireGuard driver
+    #[test]
+    fn replay_window() {
+        const T_LIM: u64 = WireGuardReceiver::WINDOW + 1;
+        let mut receiver = WireGuardReceiver::new();
+        for (counter, accepted) in [
+            (0, true),
+            (1, true),
+            (1, false),
+            (9, true),
+            (8, true),
+            (7, true),
+            (7, false),
+            (T_LIM, true),
+            (T_LIM - 1, true),
+            (T_LIM - 1, false),
+            (T_LIM - 2, true),
+            (2, true),
+            (2, false),
+            (T_LIM + 16, true),
+            (3, false),
+            (T_LIM + 16, false),
+            (T_LIM * 4, true),
+            (T_LIM * 4 - (T_LIM - 1), true),
+            (10, false),
+            (T_LIM * 4 - T_LIM, false),
+            (T_LIM * 4 - (T_LIM + 1), false),
+            (T_LIM * 4 - (T_LIM - 2), true),
+            (T_LIM * 4 + 1 - T_LIM, false),
+            (0, false),
+            (REJECT_AFTER_MESSAGES, false),
+            (REJECT_AFTER_MESSAGES - 1, true),
+            (REJECT_AFTER_MESSAGES, false),
+            (REJECT_AFTER_MESSAGES - 1, false),
+            (REJECT_AFTER_MESSAGES - 2, true),
+            (REJECT_AFTER_MESSAGES + 1, false),
+            (REJECT_AFTER_MESSAGES + 2, false),
+            (REJECT_AFTER_MESSAGES - 2, false),
+            (REJECT_AFTER_MESSAGES - 3, true),
+            (0, false),
+        ] {
+            assert_eq!(
+                receiver.accept(counter).is_ok(),
+                accepted,
+                "counter {counter}"
+            );
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let mut receiver = WireGuardReceiver::new();
+        receiver.accept(5).unwrap();
+        assert!(matches!(receiver.accept(5), Err(Error::Replay { seq: 5 })));
+        receiver.accept(WireGuardReceiver::WINDOW + 100).unwrap();
+        assert!(matches!(
+            receiver.accept(5),
+            Err(Error::OutsideWindow { seq: 5 })
+        ));
+        receiver.accept(100).unwrap();
+    }
+
+    #[test]
+    fn jump_clears_window() {
+        let mut receiver = WireGuardReceiver::new();
+        for counter in 0..200 {
+            receiver.accept(counter).unwrap();
+        }
+        // a jump of more than the whole bitmap leaves no stale bits behind
+        let far = 200 + 3 * 8192;
+        receiver.accept(far).unwrap();
+        for counter in (far - WireGuardReceiver::WINDOW..far).step_by(97) {
+            receiver.accept(counter).unwrap();
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use chacha20::cipher::consts::U10;
+use chacha20::cipher::generic_array::GenericArray;
+
+use crate::{NONCE_LEN, Nonce};
+
+/// Length of an XChaCha20-Poly1305 nonce.
+pub const XCHACHA_NONCE_LEN: usize = 24;
+
+/// Helpers for the XChaCha20 construction from draft-irtf-cfrg-xchacha.
+///
+/// The first 16 bytes of the nonce are fed to HChaCha20 to derive a subkey, and the
+/// remaining 8 bytes form the nonce used with that subkey.
+impl Nonce<XCHACHA_NONCE_LEN> {
+    /// Return the 16 bytes fed to HChaCha20.
+    pub fn hchacha_input(&self) -> &[u8; 16] {
+        self.0[..16].try_into().expect("nonce is 24 bytes")
+    }
+
+    /// Return the 8 trailing bytes used with the derived subkey.
+    pub fn tail(&self) -> &[u8; 8] {
+        self.0[16..].try_into().expect("nonce is 24 bytes")
+    }
+
+    /// Derive the ChaCha20 subkey for `key` using HChaCha20.
+    pub fn xchacha_subkey(&self, key: &[u8; 32]) -> [u8; 32] {
+        chacha20::hchacha::<U10>(
+            GenericArray::from_slice(key),
+            GenericArray::from_slice(self.hchacha_input()),
+        )
+        .into()
+    }
+
+    /// Return the 12-byte ChaCha20 nonce used with the subkey.
+    ///
+    /// This is 4 zero bytes followed by the [`Self::tail`].
+    pub fn chacha_nonce(&self) -> Nonce<NONCE_LEN> {
+        let mut buf = [0u8; NONCE_LEN];
+        buf[4..].copy_from_slice(self.tail());
+        Nonce(buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::Iv;
+    use crate::aead::{AeadCipher, AeadKey};
+    use hex_literal::hex;
+
+    // draft-irtf-cfrg-xchacha-03 section 2.2.1
+    #[test]
+    fn hchacha20() {
+        let key = hex!("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f");
+        let mut nonce = [0u8; XCHACHA_NONCE_LEN];
+        nonce[..16].copy_from_slice(&hex!("000000090000004a0000000031415927"));
+        let nonce = Nonce(nonce);
+
+        assert_eq!(
+            nonce.xchacha_subkey(&key),
+            hex!("82413b4227b27bfed30e42508a877d73a0f9e4d58a74a853c12ec41326d3ecdc")
+        );
+    }
+
+    // draft-irtf-cfrg-xchacha-03 appendix A.3.1
+    #[test]
+    fn split() {
+        let iv = Iv::new(hex!("404142434445464748494a4b4c4d4e4f5051525354555657"));
+        let nonce = Nonce::new(&iv, 0);
+
+        assert_eq!(
+            nonce.hchacha_input(),
+            &hex!("404142434445464748494a4b4c4d4e4f")
+        );
+        assert_eq!(nonce.tail(), &hex!("5051525354555657"));
+        assert_eq!(
+            nonce.chacha_nonce().to_array(),
+            hex!("000000005051525354555657")
+        );
+
+        let key = hex!("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f");
+        let mut payload = *b"Ladies and Gentlemen of the class of '99: If I could offer you only \
+            one tip for the future, sunscreen would be it.";
+        let tag = AeadKey::new(AeadCipher::ChaCha20Poly1305, &nonce.xchacha_subkey(&key))
+            .unwrap()
+            .seal(
+                &nonce.chacha_nonce(),
+                &hex!("50515253c0c1c2c3c4c5c6c7"),
+                &mut payload,
+            );
+        assert_eq!(
+            payload,
+            hex!(
+                "bd6d179d3e83d43b9576579493c0e939572a1700252bfaccbed2902c21396cbb"
+                "731c7f1b0b4aa6440bf3a82f4eda7e39ae64c6708c54c216cb96b72e1213b452"
+                "2f8c9ba40db5d945b11b69b982c1bb9e3f3fac2bc369488f76b2383565d3fff9"
+                "21f9664c97637da9768812f615c68b13b52e"
+            )
+        );
+        assert_eq!(tag, hex!("c0875924c1c7987947deafd8780acf49"));
+
+        let nonce = Nonce::new(&iv, 1);
+        assert_eq!(nonce.tail(), &hex!("5051525354555656"));
+        assert_eq!(
+            nonce.hchacha_input(),
+            &hex!("404142434445464748494a4b4c4d4e4f")
+        );
+    }
+}